@@ -0,0 +1,62 @@
+#![allow(dead_code)]
+
+use fluid_simulation::sph::{Real, SphConfig, SphSimulation, Vector};
+use nannou::geom::Rect;
+
+pub const DT: Real = 1.0 / 240.0;
+const COLUMN_ROWS: usize = 48;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Scene {
+    RestingColumn,
+    DamBreak,
+}
+
+impl Scene {
+    pub const ALL: [Self; 2] = [Self::RestingColumn, Self::DamBreak];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::RestingColumn => "resting_column",
+            Self::DamBreak => "dam_break",
+        }
+    }
+
+    fn tank_width(self) -> Real {
+        match self {
+            Self::RestingColumn => 1.0,
+            Self::DamBreak => 3.0,
+        }
+    }
+
+    pub fn build(self, particles: usize, configure: impl FnOnce(&mut SphConfig)) -> SphSimulation {
+        let rows = COLUMN_ROWS.min(particles);
+        let cols = particles.div_ceil(rows);
+        let spacing = SphConfig::new(Rect::from_w_h(1.0, 1.0)).particle_spacing;
+        let height = rows as Real * spacing * 1.5;
+        let width = (self.tank_width() * cols as Real + 4.0) * spacing;
+        let mut config = SphConfig::new(Rect::from_w_h(width, height));
+        configure(&mut config);
+
+        let mut simulation = SphSimulation::new(config);
+        let origin = Vector::new(config.bounds.left(), config.bounds.bottom())
+            + Vector::splat(2.0 * spacing);
+        simulation.seed_block(cols, rows, origin);
+        simulation
+    }
+}
+
+pub fn settle(simulation: &mut SphSimulation, steps: usize) {
+    for _ in 0..steps {
+        simulation.step(DT, None);
+    }
+}
+
+pub fn thread_counts() -> Vec<usize> {
+    let available = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let mut counts = std::iter::successors(Some(1), |threads| Some(threads * 2))
+        .take_while(|threads| *threads < available)
+        .collect::<Vec<_>>();
+    counts.push(available);
+    counts
+}
@@ -0,0 +1,38 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fluid_simulation::sph::{Real, SphConfig, SphSimulation, Vector};
+use nannou::geom::Rect;
+
+const PARTICLE_COUNTS: [usize; 3] = [10_000, 100_000, 400_000];
+
+fn seeded_simulation(particles: usize, parallel: bool) -> SphSimulation {
+    let side = (particles as Real).sqrt().ceil() as usize;
+    let probe = SphConfig::new(Rect::from_w_h(1.0, 1.0));
+    let width = side as Real * probe.particle_spacing * 1.25;
+    let mut config = SphConfig::new(Rect::from_w_h(width, width));
+    config.parallel_grid_rebuild = parallel;
+
+    let mut simulation = SphSimulation::new(config);
+    let origin =
+        Vector::new(config.bounds.left(), config.bounds.bottom()) + Vector::splat(0.1 * width);
+    simulation.seed_block(side, particles.div_ceil(side), origin);
+    simulation
+}
+
+fn grid_rebuild(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grid_rebuild");
+    group.sample_size(20);
+
+    for particles in PARTICLE_COUNTS {
+        for (label, parallel) in [("serial", false), ("parallel", true)] {
+            let mut simulation = seeded_simulation(particles, parallel);
+            group.bench_with_input(BenchmarkId::new(label, particles), &particles, |b, _| {
+                b.iter(|| simulation.rebuild_neighbors())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, grid_rebuild);
+criterion_main!(benches);
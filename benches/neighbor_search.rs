@@ -0,0 +1,32 @@
+mod common;
+
+use common::{DT, Scene, settle};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fluid_simulation::sph::NeighborSearchKind;
+
+const PARTICLE_COUNTS: [usize; 2] = [10_000, 40_000];
+const SETTLE_STEPS: usize = 720;
+
+fn neighbor_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.sample_size(10);
+
+    for scene in Scene::ALL {
+        for particles in PARTICLE_COUNTS {
+            for (search_name, kind) in [
+                ("uniform_grid", NeighborSearchKind::UniformGrid),
+                ("verlet_list", NeighborSearchKind::VerletList),
+            ] {
+                let mut simulation = scene.build(particles, |config| config.neighbor_search = kind);
+                settle(&mut simulation, SETTLE_STEPS);
+                let id = BenchmarkId::new(format!("{}/{search_name}", scene.label()), particles);
+                group.bench_function(id, |b| b.iter(|| simulation.step(DT, None)));
+            }
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, neighbor_search);
+criterion_main!(benches);
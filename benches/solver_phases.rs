@@ -0,0 +1,56 @@
+mod common;
+
+use common::{DT, Scene, settle, thread_counts};
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use fluid_simulation::sph::SphSimulation;
+
+const PARTICLE_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+const SETTLE_STEPS: usize = 2;
+
+type Phase = (&'static str, fn(&mut SphSimulation));
+
+const PHASES: [Phase; 5] = [
+    ("grid_rebuild", SphSimulation::rebuild_neighbors),
+    ("densities", SphSimulation::compute_densities),
+    ("pressures", SphSimulation::compute_pressures),
+    ("accelerations", |simulation| {
+        simulation.compute_accelerations(None)
+    }),
+    ("integrate", |simulation| simulation.integrate(DT)),
+];
+
+fn solver_phases(c: &mut Criterion) {
+    let mut group = c.benchmark_group("phase");
+    group.sample_size(10);
+
+    for scene in Scene::ALL {
+        for particles in PARTICLE_COUNTS {
+            let mut simulation = scene.build(particles, |_| {});
+            settle(&mut simulation, SETTLE_STEPS);
+
+            for threads in thread_counts() {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .expect("benchmark thread pool");
+
+                for (phase, run) in PHASES {
+                    let id = BenchmarkId::new(
+                        format!("{phase}/{}/{threads}_threads", scene.label()),
+                        particles,
+                    );
+                    group.bench_function(id, |b| {
+                        pool.install(|| {
+                            b.iter_batched_ref(|| simulation.clone(), run, BatchSize::LargeInput)
+                        })
+                    });
+                }
+            }
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, solver_phases);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use fluid_simulation::sph::{Real, SimulationStats, SphConfig, SphSimulation, Vector};
+use nannou::prelude::*;
+use std::time::Instant;
+
+const FIXED_TIME_STEP: Real = 1.0 / 240.0;
+const DOMAIN_WIDTH: Real = 900.0 / 110.0;
+const DOMAIN_HEIGHT: Real = 900.0 / 110.0;
+const DEFAULT_STEPS: usize = 2400;
+const DEFAULT_REPORT_INTERVAL: usize = 240;
+const DEFAULT_PARTICLES_X: usize = 36;
+const DEFAULT_PARTICLES_Y: usize = 48;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let steps = parse_arg(args.next(), DEFAULT_STEPS);
+    let report_interval = parse_arg(args.next(), DEFAULT_REPORT_INTERVAL).max(1);
+    let particles_x = parse_arg(args.next(), DEFAULT_PARTICLES_X);
+    let particles_y = parse_arg(args.next(), DEFAULT_PARTICLES_Y);
+
+    let bounds = Rect::from_w_h(DOMAIN_WIDTH, DOMAIN_HEIGHT);
+    let mut config = SphConfig::new(bounds);
+    config.deterministic = true;
+    let mut simulation = SphSimulation::new(config);
+    let origin = Vector::new(
+        bounds.left() + bounds.w() * 0.14,
+        bounds.bottom() + bounds.h() * 0.12,
+    );
+    simulation.seed_block(particles_x, particles_y, origin);
+
+    let started = Instant::now();
+    for step in 1..=steps {
+        simulation.step(FIXED_TIME_STEP, None);
+
+        if step % report_interval == 0 || step == steps {
+            simulation.refresh_stats();
+            report(step, &simulation.stats(), simulation.state_hash());
+        }
+    }
+
+    println!(
+        "{steps} steps in {:.2} s ({:.2} ms/step)",
+        started.elapsed().as_secs_f32(),
+        started.elapsed().as_secs_f32() * 1.0e3 / steps.max(1) as f32,
+    );
+}
+
+fn parse_arg(arg: Option<String>, default: usize) -> usize {
+    arg.and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn report(step: usize, stats: &SimulationStats, state_hash: u64) {
+    println!(
+        "step {step}: particles {}  threads {}  max speed {:.2} m/s  density {:.2} rho0  mechanical {:.0} J  hash {state_hash:016x}",
+        stats.particle_count,
+        stats.threads,
+        stats.max_speed,
+        stats.max_density_ratio,
+        stats.energy.mechanical(),
+    );
+
+    let phases = stats
+        .timings
+        .entries()
+        .iter()
+        .map(|(name, millis)| format!("{name} {millis:.3}"))
+        .collect::<Vec<_>>()
+        .join("  ");
+    println!("  ms: {phases}  total {:.3}", stats.timings.total());
+}
@@ -0,0 +1,65 @@
+use fluid_simulation::sph::{Bounds3, Real, Sph3dConfig, Sph3dSimulation, Sph3dStats, Vector3};
+use std::time::Instant;
+
+const FIXED_TIME_STEP: Real = 1.0 / 240.0;
+const DOMAIN_SIZE: [Real; 3] = [3.0, 3.0, 1.0];
+const DEFAULT_STEPS: usize = 1200;
+const DEFAULT_REPORT_INTERVAL: usize = 240;
+const DEFAULT_PARTICLES_X: usize = 16;
+const DEFAULT_PARTICLES_Y: usize = 24;
+const DEFAULT_PARTICLES_Z: usize = 12;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let steps = parse_arg(args.next(), DEFAULT_STEPS);
+    let report_interval = parse_arg(args.next(), DEFAULT_REPORT_INTERVAL).max(1);
+    let particles_x = parse_arg(args.next(), DEFAULT_PARTICLES_X);
+    let particles_y = parse_arg(args.next(), DEFAULT_PARTICLES_Y);
+    let particles_z = parse_arg(args.next(), DEFAULT_PARTICLES_Z);
+    let output = args.next();
+
+    let bounds = Bounds3::from_size(Vector3::from(DOMAIN_SIZE));
+    let mut config = Sph3dConfig::new(bounds);
+    config.deterministic = true;
+    let mut simulation = Sph3dSimulation::new(config);
+    let origin = bounds.min + 2.0 * Vector3::splat(config.particle_spacing);
+    simulation.seed_block(particles_x, particles_y, particles_z, origin);
+
+    let started = Instant::now();
+    for step in 1..=steps {
+        simulation.step(FIXED_TIME_STEP);
+
+        if step % report_interval == 0 || step == steps {
+            report(step, &simulation.stats());
+        }
+    }
+
+    println!(
+        "{steps} steps in {:.2} s ({:.2} ms/step)",
+        started.elapsed().as_secs_f32(),
+        started.elapsed().as_secs_f32() * 1.0e3 / steps.max(1) as f32,
+    );
+
+    if let Some(path) = output {
+        match simulation.write_particles_vtp(&path) {
+            Ok(()) => println!("wrote {path}"),
+            Err(error) => eprintln!("failed to write {path}: {error}"),
+        }
+    }
+}
+
+fn parse_arg(arg: Option<String>, default: usize) -> usize {
+    arg.and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn report(step: usize, stats: &Sph3dStats) {
+    println!(
+        "step {step}: particles {}  threads {}  max speed {:.2} m/s  density {:.2} rho0  energy {:.0} J  volume drift {:+.2}%",
+        stats.particle_count,
+        stats.threads,
+        stats.max_speed,
+        stats.max_density_ratio,
+        stats.kinetic_energy + stats.potential_energy + stats.elastic_energy,
+        stats.volume_drift * 100.0,
+    );
+}
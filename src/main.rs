@@ -1,11 +1,13 @@
-mod sph;
-
+use fluid_simulation::sph::{
+    Bounds3, Interaction, InteractionMode, ParticleClass, PorousShape, PorousZone, Real,
+    Sph3dConfig, Sph3dSimulation, Sph3dStats, SphConfig, SphSimulation, SurfaceField, Vector,
+    Vector3, from_f32, from_rect, from_vec2, to_f32, to_rect, to_vec2,
+};
 use nannou::prelude::*;
-use sph::{Interaction, InteractionMode, SphConfig, SphSimulation};
 
 const WINDOW_WIDTH: u32 = 900;
 const WINDOW_HEIGHT: u32 = 900;
-const FIXED_TIME_STEP: f32 = 1.0 / 240.0;
+const FIXED_TIME_STEP: Real = 1.0 / 240.0;
 const MAX_STEPS_PER_FRAME: usize = 6;
 const PIXELS_PER_METER: f32 = 110.0;
 const MAX_FRAME_DELTA: f32 = 1.0 / 30.0;
@@ -13,12 +15,41 @@ const INITIAL_PARTICLES_X: usize = 36;
 const INITIAL_PARTICLES_Y: usize = 48;
 const PARTICLE_DRAW_RESOLUTION: f32 = 6.0;
 const INTERACTION_DRAW_RESOLUTION: f32 = 24.0;
+const SURFACE_GRID_RESOLUTION: usize = 160;
+const SURFACE_ISO_LEVEL: Real = 0.5;
+const VORTICITY_COLOR_RANGE: f32 = 25.0;
+const POROUS_BED_POROSITY: Real = 0.4;
+const POROUS_BED_PERMEABILITY: Real = 2.0e-3;
+const POROUS_SDF_DRAW_RESOLUTION: usize = 64;
+const VOLUME_DEPTH: Real = 1.0;
+const VOLUME_PARTICLES: [usize; 3] = [16, 24, 10];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorMode {
+    Speed,
+    Vorticity,
+    Class,
+}
+
+impl ColorMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Speed => Self::Vorticity,
+            Self::Vorticity => Self::Class,
+            Self::Class => Self::Speed,
+        }
+    }
+}
 
 struct Model {
     _window: window::Id,
     simulation: SphSimulation,
-    accumulator: f32,
+    accumulator: Real,
     steps_last_frame: usize,
+    show_surface: bool,
+    color_mode: ColorMode,
+    volume: Option<Sph3dSimulation>,
+    volume_stats: Sph3dStats,
 }
 
 fn main() {
@@ -33,6 +64,7 @@ fn model(app: &App) -> Model {
         .min_size(WINDOW_WIDTH, WINDOW_HEIGHT)
         .max_size(WINDOW_WIDTH, WINDOW_HEIGHT)
         .view(view)
+        .key_pressed(key_pressed)
         .build()
         .unwrap();
 
@@ -41,7 +73,7 @@ fn model(app: &App) -> Model {
     let bounds = simulation_bounds();
     let mut simulation = SphSimulation::new(SphConfig::new(bounds));
     let config = *simulation.config();
-    let origin = vec2(
+    let origin = Vector::new(
         config.bounds.left() + config.bounds.w() * 0.14,
         config.bounds.bottom() + config.bounds.h() * 0.12,
     );
@@ -53,17 +85,76 @@ fn model(app: &App) -> Model {
         simulation,
         accumulator: 0.0,
         steps_last_frame: 0,
+        show_surface: false,
+        color_mode: ColorMode::Speed,
+        volume: None,
+        volume_stats: Sph3dStats::default(),
+    }
+}
+
+fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+    match key {
+        Key::S => model.show_surface = !model.show_surface,
+        Key::C => model.color_mode = model.color_mode.next(),
+        Key::P => toggle_porous_bed(&mut model.simulation),
+        Key::D => toggle_volume(model),
+        _ => {}
+    }
+}
+
+fn toggle_porous_bed(simulation: &mut SphSimulation) {
+    if !simulation.porous_zones().is_empty() {
+        simulation.clear_porous_zones();
+        return;
     }
+
+    let bounds = simulation.config().bounds;
+    let bed = Rect::from_x_y_w_h(
+        bounds.x() + bounds.w() * 0.2,
+        bounds.bottom() + bounds.h() * 0.15,
+        bounds.w() * 0.3,
+        bounds.h() * 0.3,
+    );
+    simulation.add_porous_zone(PorousZone::new(
+        PorousShape::Rect(bed),
+        POROUS_BED_POROSITY,
+        POROUS_BED_PERMEABILITY,
+    ));
+}
+
+fn toggle_volume(model: &mut Model) {
+    if model.volume.take().is_some() {
+        return;
+    }
+
+    let bounds = model.simulation.config().bounds;
+    let config = Sph3dConfig::new(Bounds3 {
+        min: Vector3::new(bounds.left(), bounds.bottom(), -0.5 * VOLUME_DEPTH),
+        max: Vector3::new(bounds.right(), bounds.top(), 0.5 * VOLUME_DEPTH),
+    });
+    let mut volume = Sph3dSimulation::new(config);
+    let [cols, rows, layers] = VOLUME_PARTICLES;
+    let origin = Vector3::new(
+        bounds.left() + bounds.w() * 0.14,
+        bounds.bottom() + bounds.h() * 0.12,
+        config.bounds.min.z + 2.0 * config.particle_spacing,
+    );
+    volume.seed_block(cols, rows, layers, origin);
+    model.volume_stats = volume.stats();
+    model.volume = Some(volume);
 }
 
 fn update(app: &App, model: &mut Model, update: Update) {
-    model.accumulator += update.since_last.as_secs_f32().min(MAX_FRAME_DELTA);
+    model.accumulator += from_f32(update.since_last.as_secs_f32().min(MAX_FRAME_DELTA));
 
     let interaction = active_interaction(app, *model.simulation.config());
     let mut steps = 0;
 
     while model.accumulator >= FIXED_TIME_STEP && steps < MAX_STEPS_PER_FRAME {
-        model.simulation.step(FIXED_TIME_STEP, interaction);
+        match &mut model.volume {
+            Some(volume) => volume.step(FIXED_TIME_STEP),
+            None => model.simulation.step(FIXED_TIME_STEP, interaction),
+        }
         model.accumulator -= FIXED_TIME_STEP;
         steps += 1;
     }
@@ -74,7 +165,15 @@ fn update(app: &App, model: &mut Model, update: Update) {
 
     model.steps_last_frame = steps;
     if steps > 0 {
-        model.simulation.refresh_stats();
+        match &model.volume {
+            Some(volume) => model.volume_stats = volume.stats(),
+            None => {
+                model.simulation.refresh_stats();
+                if model.color_mode == ColorMode::Vorticity {
+                    model.simulation.refresh_vorticities();
+                }
+            }
+        }
     }
 }
 
@@ -82,36 +181,27 @@ fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
     let win = app.window_rect();
     let config = *model.simulation.config();
+    let bounds = to_rect(config.bounds);
 
     draw.background().color(srgba(0.03, 0.04, 0.07, 1.0));
 
     draw.rect()
-        .xy(world_to_screen(config.bounds.xy()))
-        .w_h(
-            config.bounds.w() * PIXELS_PER_METER,
-            config.bounds.h() * PIXELS_PER_METER,
-        )
+        .xy(bounds.xy() * PIXELS_PER_METER)
+        .w_h(bounds.w() * PIXELS_PER_METER, bounds.h() * PIXELS_PER_METER)
         .no_fill()
         .stroke(srgba(0.65, 0.78, 0.95, 0.65))
         .stroke_weight(2.0);
 
-    for ((position, velocity), density) in model
-        .simulation
-        .positions()
-        .iter()
-        .zip(model.simulation.velocities())
-        .zip(model.simulation.densities())
-    {
-        let speed = velocity.length();
-        let density_ratio = (*density / config.rest_density).clamp(0.85, 1.35);
-        let hue = map_range(speed, 0.0, 8.0, 0.56, 0.03).clamp(0.03, 0.56);
-        let lightness = map_range(density_ratio, 0.85, 1.35, 0.44, 0.68).clamp(0.40, 0.72);
+    if let Some(volume) = &model.volume {
+        draw_volume(&draw, volume);
+    } else {
+        draw_porous_zones(&draw, &model.simulation);
 
-        draw.ellipse()
-            .xy(world_to_screen(*position))
-            .radius(config.particle_radius * PIXELS_PER_METER)
-            .resolution(PARTICLE_DRAW_RESOLUTION)
-            .hsla(hue, 0.78, lightness, 0.95);
+        if model.show_surface {
+            draw_surface(&draw, &model.simulation);
+        } else {
+            draw_particles(&draw, &model.simulation, model.color_mode);
+        }
     }
 
     if let Some(interaction) = active_interaction(app, config) {
@@ -122,39 +212,230 @@ fn view(app: &App, model: &Model, frame: Frame) {
 
         draw.ellipse()
             .xy(world_to_screen(interaction.position))
-            .radius(interaction.radius * PIXELS_PER_METER)
+            .radius(to_f32(interaction.radius) * PIXELS_PER_METER)
             .resolution(INTERACTION_DRAW_RESOLUTION)
             .no_fill()
             .stroke(ring_color)
             .stroke_weight(2.0);
     }
 
+    let hud = match model.volume {
+        Some(_) => volume_hud(app, model),
+        None => simulation_hud(app, model),
+    };
+
+    draw.text(&hud)
+        .left_justify()
+        .color(WHITE)
+        .font_size(16)
+        .w_h(660.0, 170.0)
+        .x_y(win.left() + 335.0, win.top() - 78.0);
+
+    draw.to_frame(app, &frame).unwrap();
+}
+
+fn simulation_hud(app: &App, model: &Model) -> String {
     let stats = model.simulation.stats();
-    let hud = format!(
-        "WCSPH + rayon\nparticles: {}  threads: {}  fps: {:.0}\nmax speed: {:.2} m/s  density: {:.2} rho0  steps/frame: {}\nmouse: left attracts, right repels",
+    let energy = stats.energy;
+    let lost = energy.total_dissipation;
+    let timings = stats.timings;
+    let core_timings = timings.neighbors
+        + timings.density
+        + timings.pressure
+        + timings.accelerations
+        + timings.integrate;
+
+    format!(
+        "WCSPH + rayon\nparticles: {}  threads: {}  fps: {:.0}\nmax speed: {:.2} m/s  density: {:.2} rho0  steps/frame: {}\nenergy: kin {:.0}  pot {:.0}  eos {:.0} J  volume drift: {:+.2}%\nlost: visc {:.0}  av {:.0}  porous {:.0}  xsph {:.0}  damp {:.0}  clamp {:.0}  wall {:.0} J\nstep ms: grid {:.2}  dens {:.2}  pres {:.2}  acc {:.2}  int {:.2}  other {:.2}  total {:.2}\nmouse: left attracts, right repels  S: outline  C: color  P: porous bed  D: 3D",
         stats.particle_count,
         stats.threads,
         app.fps(),
         stats.max_speed,
         stats.max_density_ratio,
         model.steps_last_frame,
+        energy.kinetic,
+        energy.potential,
+        energy.elastic,
+        energy.volume_drift * 100.0,
+        lost.viscosity,
+        lost.artificial_viscosity,
+        lost.porous,
+        lost.xsph,
+        lost.velocity_damping,
+        lost.velocity_clamp,
+        lost.boundary,
+        timings.neighbors,
+        timings.density,
+        timings.pressure,
+        timings.accelerations,
+        timings.integrate,
+        timings.total() - core_timings,
+        timings.total(),
+    )
+}
+
+fn volume_hud(app: &App, model: &Model) -> String {
+    let stats = model.volume_stats;
+
+    format!(
+        "WCSPH 3D + rayon\nparticles: {}  threads: {}  fps: {:.0}\nmax speed: {:.2} m/s  density: {:.2} rho0  steps/frame: {}\nenergy: kin {:.0}  pot {:.0}  eos {:.0} J  volume drift: {:+.2}%\nxy projection, depth as lightness  D: 2D",
+        stats.particle_count,
+        stats.threads,
+        app.fps(),
+        stats.max_speed,
+        stats.max_density_ratio,
+        model.steps_last_frame,
+        stats.kinetic_energy,
+        stats.potential_energy,
+        stats.elastic_energy,
+        stats.volume_drift * 100.0,
+    )
+}
+
+fn draw_particles(draw: &Draw, simulation: &SphSimulation, color_mode: ColorMode) {
+    let config = simulation.config();
+
+    for ((((position, velocity), density), vorticity), class) in simulation
+        .positions()
+        .iter()
+        .zip(simulation.velocities())
+        .zip(simulation.densities())
+        .zip(simulation.vorticities())
+        .zip(simulation.particle_classes())
+    {
+        let (hue, lightness) = match color_mode {
+            ColorMode::Speed => {
+                let speed = velocity.length();
+                let density_ratio = (*density / config.rest_density).clamp(0.85, 1.35);
+                (
+                    map_range(speed, 0.0, 8.0, 0.56, 0.03).clamp(0.03, 0.56),
+                    map_range(density_ratio, 0.85, 1.35, 0.44, 0.68).clamp(0.40, 0.72),
+                )
+            }
+            ColorMode::Vorticity => vorticity_color(to_f32(*vorticity)),
+            ColorMode::Class => class_color(*class),
+        };
+
+        draw.ellipse()
+            .xy(world_to_screen(*position))
+            .radius(to_f32(config.particle_radius) * PIXELS_PER_METER)
+            .resolution(PARTICLE_DRAW_RESOLUTION)
+            .hsla(hue, 0.78, lightness, 0.95);
+    }
+}
+
+fn draw_volume(draw: &Draw, volume: &Sph3dSimulation) {
+    let config = volume.config();
+    let positions = volume.positions();
+    let velocities = volume.velocities();
+    let mut order = (0..positions.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| positions[*a].z.total_cmp(&positions[*b].z));
+
+    for index in order {
+        let position = positions[index];
+        let depth =
+            (position.z - config.bounds.min.z) / (config.bounds.max.z - config.bounds.min.z);
+        let speed = velocities[index].length();
+
+        draw.ellipse()
+            .xy(world_to_screen(Vector::new(position.x, position.y)))
+            .radius(to_f32(config.particle_radius) * PIXELS_PER_METER)
+            .resolution(PARTICLE_DRAW_RESOLUTION)
+            .hsla(
+                map_range(speed, 0.0, 8.0, 0.56, 0.03).clamp(0.03, 0.56),
+                0.78,
+                map_range(depth, 0.0, 1.0, 0.30, 0.70).clamp(0.30, 0.70),
+                0.95,
+            );
+    }
+}
+
+fn draw_porous_zones(draw: &Draw, simulation: &SphSimulation) {
+    let fill = srgba(0.55, 0.45, 0.30, 0.22);
+    let bounds = simulation.config().bounds;
+
+    for zone in simulation.porous_zones() {
+        match zone.shape {
+            PorousShape::Rect(rect) => {
+                let rect = to_rect(rect);
+                draw.rect()
+                    .xy(rect.xy() * PIXELS_PER_METER)
+                    .w_h(rect.w() * PIXELS_PER_METER, rect.h() * PIXELS_PER_METER)
+                    .color(fill);
+            }
+            PorousShape::Circle { center, radius } => {
+                draw.ellipse()
+                    .xy(world_to_screen(center))
+                    .radius(to_f32(radius) * PIXELS_PER_METER)
+                    .color(fill);
+            }
+            PorousShape::Sdf(_) => {
+                let cell = bounds.w() / POROUS_SDF_DRAW_RESOLUTION as Real;
+                for row in 0..POROUS_SDF_DRAW_RESOLUTION {
+                    for col in 0..POROUS_SDF_DRAW_RESOLUTION {
+                        let center = Vector::new(
+                            bounds.left() + (col as Real + 0.5) * cell,
+                            bounds.bottom() + (row as Real + 0.5) * cell,
+                        );
+                        if zone.shape.contains(center) {
+                            draw.rect()
+                                .xy(world_to_screen(center))
+                                .w_h(
+                                    to_f32(cell) * PIXELS_PER_METER,
+                                    to_f32(cell) * PIXELS_PER_METER,
+                                )
+                                .color(fill);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn vorticity_color(vorticity: f32) -> (f32, f32) {
+    let hue = if vorticity >= 0.0 { 0.0 } else { 0.62 };
+    let lightness = map_range(vorticity.abs(), 0.0, VORTICITY_COLOR_RANGE, 0.25, 0.65);
+
+    (hue, lightness.clamp(0.25, 0.65))
+}
+
+fn class_color(class: ParticleClass) -> (f32, f32) {
+    match class {
+        ParticleClass::Interior => (0.60, 0.42),
+        ParticleClass::FreeSurface => (0.50, 0.72),
+        ParticleClass::NearWall => (0.10, 0.55),
+        ParticleClass::Isolated => (0.0, 0.60),
+    }
+}
+
+fn draw_surface(draw: &Draw, simulation: &SphSimulation) {
+    let surface = simulation.extract_surface(
+        SurfaceField::ColorField {
+            iso_level: SURFACE_ISO_LEVEL,
+        },
+        SURFACE_GRID_RESOLUTION,
+        SURFACE_GRID_RESOLUTION,
     );
 
-    draw.text(&hud)
-        .left_justify()
-        .color(WHITE)
-        .font_size(16)
-        .w_h(470.0, 110.0)
-        .x_y(win.left() + 240.0, win.top() - 48.0);
+    for polyline in &surface.polylines {
+        let mut points = polyline.points.clone();
+        if polyline.closed {
+            points.extend(polyline.points.first().copied());
+        }
 
-    draw.to_frame(app, &frame).unwrap();
+        draw.polyline()
+            .weight(2.5)
+            .points(points.into_iter().map(world_to_screen))
+            .color(srgba(0.35, 0.72, 0.98, 0.95));
+    }
 }
 
-fn simulation_bounds() -> Rect {
-    Rect::from_w_h(
+fn simulation_bounds() -> Rect<Real> {
+    from_rect(Rect::from_w_h(
         WINDOW_WIDTH as f32 / PIXELS_PER_METER,
         WINDOW_HEIGHT as f32 / PIXELS_PER_METER,
-    )
+    ))
 }
 
 fn active_interaction(app: &App, config: SphConfig) -> Option<Interaction> {
@@ -179,10 +460,10 @@ fn active_interaction(app: &App, config: SphConfig) -> Option<Interaction> {
     }
 }
 
-fn screen_to_world(position: Vec2) -> Vec2 {
-    position / PIXELS_PER_METER
+fn screen_to_world(position: Vec2) -> Vector {
+    from_vec2(position / PIXELS_PER_METER)
 }
 
-fn world_to_screen(position: Vec2) -> Vec2 {
-    position * PIXELS_PER_METER
+fn world_to_screen(position: Vector) -> Vec2 {
+    to_vec2(position) * PIXELS_PER_METER
 }
@@ -1,8 +1,57 @@
 use nannou::prelude::*;
 use rayon::prelude::*;
-use std::f32::consts::PI;
-
-const EPSILON: f32 = 1.0e-6;
+use real::consts::PI;
+
+mod boundary;
+mod classification;
+mod correction;
+mod density;
+mod determinism;
+mod diagnostics;
+mod export;
+mod field;
+mod granular;
+mod linalg;
+mod material;
+mod neighbors;
+mod ordering;
+mod porous;
+mod profiling;
+mod real;
+mod rotation;
+mod shifting;
+mod simd;
+mod solid;
+mod solver3d;
+mod sort;
+mod surface;
+mod tension;
+mod vorticity;
+
+use boundary::clamp_magnitude;
+pub use classification::ParticleClass;
+use correction::symmetric_correction;
+pub use density::{DensityDiffusion, DensityMethod, DensityReinitialization};
+pub use determinism::SplitMix64;
+pub use diagnostics::{DissipationBreakdown, EnergyReport};
+use diagnostics::{DissipativeTerms, kinetic_energy};
+pub use field::{EulerianField, FIELD_CHANNELS};
+use linalg::{Matrix2, SymmetricMatrix2};
+pub use material::{GranularMaterial, Phase, SolidMaterial, SolidModel};
+pub use neighbors::NeighborSearchKind;
+use neighbors::{NeighborGrid, NeighborSearch};
+pub use ordering::ParticleOrdering;
+pub use porous::{PorousShape, PorousZone};
+use profiling::PhaseTimer;
+pub use profiling::PhaseTimings;
+pub use real::{Real, Vector, Vector3, from_f32, from_rect, from_vec2, to_f32, to_rect, to_vec2};
+use simd::{NeighborBatch, PairKernel};
+use solid::SolidState;
+pub use solver3d::{Bounds3, Sph3dConfig, Sph3dSimulation, Sph3dStats};
+pub use surface::{FreeSurface, Polyline, SurfaceField};
+pub use tension::{SurfaceTensionModel, Wall, WallMaterial};
+
+const EPSILON: Real = 1.0e-6;
 const MIN_PAR_CHUNK: usize = 64;
 
 #[derive(Clone, Copy, Debug)]
@@ -13,51 +62,85 @@ pub enum InteractionMode {
 
 #[derive(Clone, Copy, Debug)]
 pub struct Interaction {
-    pub position: Vec2,
-    pub radius: f32,
-    pub strength: f32,
+    pub position: Vector,
+    pub radius: Real,
+    pub strength: Real,
     pub mode: InteractionMode,
 }
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct SimulationStats {
     pub particle_count: usize,
-    pub max_speed: f32,
-    pub max_density_ratio: f32,
+    pub max_speed: Real,
+    pub max_density_ratio: Real,
     pub threads: usize,
+    pub energy: EnergyReport,
+    pub contact_angles: [Option<Real>; 4],
+    pub timings: PhaseTimings,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct SphConfig {
-    pub bounds: Rect,
-    pub particle_spacing: f32,
-    pub particle_radius: f32,
-    pub particle_mass: f32,
-    pub smoothing_radius: f32,
-    pub rest_density: f32,
-    pub pressure_stiffness: f32,
-    pub gamma: f32,
-    pub gravity: Vec2,
-    pub viscosity: f32,
-    pub surface_tension: f32,
-    pub surface_threshold: f32,
-    pub xsph_factor: f32,
-    pub interaction_radius: f32,
-    pub interaction_strength: f32,
-    pub interaction_max_acceleration: f32,
-    pub boundary_stiffness: f32,
-    pub boundary_damping: f32,
-    pub boundary_restitution: f32,
-    pub boundary_margin: f32,
-    pub max_density_ratio: f32,
-    pub max_acceleration: f32,
-    pub max_velocity: f32,
-    pub xsph_max_velocity: f32,
-    pub velocity_damping: f32,
+    pub bounds: Rect<Real>,
+    pub particle_spacing: Real,
+    pub particle_radius: Real,
+    pub particle_mass: Real,
+    pub smoothing_radius: Real,
+    pub rest_density: Real,
+    pub density_method: DensityMethod,
+    pub density_diffusion: DensityDiffusion,
+    pub density_diffusion_coefficient: Real,
+    pub density_reinitialization: DensityReinitialization,
+    pub density_reinitialization_interval: usize,
+    pub pressure_stiffness: Real,
+    pub gamma: Real,
+    pub neighbor_search: NeighborSearchKind,
+    pub neighbor_skin: Real,
+    pub parallel_grid_rebuild: bool,
+    pub deterministic: bool,
+    pub random_seed: u64,
+    pub seed_jitter: Real,
+    pub particle_ordering: ParticleOrdering,
+    pub reorder_interval: usize,
+    pub gravity: Vector,
+    pub frame_angular_velocity: Real,
+    pub frame_center: Vector,
+    pub report_inertial_velocities: bool,
+    pub kernel_gradient_correction: bool,
+    pub kernel_correction_min_determinant: Real,
+    pub viscosity: Real,
+    pub fluid_viscosity: Real,
+    pub artificial_viscosity_alpha: Real,
+    pub artificial_viscosity_beta: Real,
+    pub surface_tension_model: SurfaceTensionModel,
+    pub surface_tension: Real,
+    pub surface_threshold: Real,
+    pub surface_eigenvalue_min: Real,
+    pub surface_eigenvalue_max: Real,
+    pub isolated_neighbor_count: usize,
+    pub xsph_factor: Real,
+    pub shifting_coefficient: Real,
+    pub shifting_max_distance: Real,
+    pub vorticity_confinement: Real,
+    pub interaction_radius: Real,
+    pub interaction_strength: Real,
+    pub interaction_max_acceleration: Real,
+    pub boundary_stiffness: Real,
+    pub boundary_damping: Real,
+    pub boundary_restitution: Real,
+    pub boundary_margin: Real,
+    pub wall_materials: [WallMaterial; 4],
+    pub granular: GranularMaterial,
+    pub solid: SolidMaterial,
+    pub max_density_ratio: Real,
+    pub max_acceleration: Real,
+    pub max_velocity: Real,
+    pub xsph_max_velocity: Real,
+    pub velocity_damping: Real,
 }
 
 impl SphConfig {
-    pub fn new(bounds: Rect) -> Self {
+    pub fn new(bounds: Rect<Real>) -> Self {
         let particle_spacing = 0.055;
         let smoothing_radius = particle_spacing * 2.1;
         let rest_density = 1000.0;
@@ -71,13 +154,41 @@ impl SphConfig {
             particle_mass: rest_density * particle_spacing * particle_spacing,
             smoothing_radius,
             rest_density,
+            density_method: DensityMethod::Summation,
+            density_diffusion: DensityDiffusion::MolteniColagrossi,
+            density_diffusion_coefficient: 0.1,
+            density_reinitialization: DensityReinitialization::None,
+            density_reinitialization_interval: 20,
             pressure_stiffness: rest_density * sound_speed * sound_speed / gamma,
             gamma,
-            gravity: vec2(0.0, -9.81),
+            neighbor_search: NeighborSearchKind::UniformGrid,
+            neighbor_skin: smoothing_radius * 0.2,
+            parallel_grid_rebuild: true,
+            deterministic: false,
+            random_seed: 0x5eed,
+            seed_jitter: 0.0,
+            particle_ordering: ParticleOrdering::None,
+            reorder_interval: 50,
+            gravity: Vector::new(0.0, -9.81),
+            frame_angular_velocity: 0.0,
+            frame_center: Vector::new(bounds.x(), bounds.y()),
+            report_inertial_velocities: false,
+            kernel_gradient_correction: false,
+            kernel_correction_min_determinant: 0.2,
             viscosity: 0.14,
+            fluid_viscosity: 1.0e-6,
+            artificial_viscosity_alpha: 0.0,
+            artificial_viscosity_beta: 0.0,
+            surface_tension_model: SurfaceTensionModel::ColorField,
             surface_tension: 0.18,
             surface_threshold: 3.0,
+            surface_eigenvalue_min: 0.2,
+            surface_eigenvalue_max: 0.75,
+            isolated_neighbor_count: 4,
             xsph_factor: 0.04,
+            shifting_coefficient: 0.0,
+            shifting_max_distance: particle_spacing * 0.2,
+            vorticity_confinement: 0.0,
             interaction_radius: 0.65,
             interaction_strength: 700.0,
             interaction_max_acceleration: 180.0,
@@ -85,6 +196,9 @@ impl SphConfig {
             boundary_damping: 18.0,
             boundary_restitution: 0.15,
             boundary_margin: particle_spacing * 0.5,
+            wall_materials: [WallMaterial::default(); 4],
+            granular: GranularMaterial::default(),
+            solid: SolidMaterial::default(),
             max_density_ratio: 2.4,
             max_acceleration: 320.0,
             max_velocity: 14.0,
@@ -92,31 +206,39 @@ impl SphConfig {
             velocity_damping: 0.9992,
         }
     }
+
+    pub fn sound_speed(&self) -> Real {
+        (self.pressure_stiffness * self.gamma / self.rest_density).sqrt()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 struct KernelSet {
-    support_radius: f32,
-    support_sq: f32,
-    poly6_coeff: f32,
-    spiky_grad_coeff: f32,
-    viscosity_laplacian_coeff: f32,
+    support_radius: Real,
+    support_sq: Real,
+    poly6_coeff: Real,
+    spiky_grad_coeff: Real,
+    viscosity_laplacian_coeff: Real,
+    cohesion_coeff: Real,
+    adhesion_coeff: Real,
 }
 
 impl KernelSet {
     #[inline]
-    fn new(smoothing_radius: f32) -> Self {
+    fn new(smoothing_radius: Real) -> Self {
         Self {
             support_radius: smoothing_radius,
             support_sq: smoothing_radius * smoothing_radius,
             poly6_coeff: 4.0 / (PI * smoothing_radius.powi(8)),
             spiky_grad_coeff: -30.0 / (PI * smoothing_radius.powi(5)),
             viscosity_laplacian_coeff: 40.0 / (PI * smoothing_radius.powi(5)),
+            cohesion_coeff: 32.0 / (PI * smoothing_radius.powi(9)),
+            adhesion_coeff: 0.007 / smoothing_radius.powf(3.25),
         }
     }
 
     #[inline]
-    fn poly6(&self, distance_sq: f32) -> f32 {
+    fn poly6(&self, distance_sq: Real) -> Real {
         if distance_sq >= self.support_sq {
             return 0.0;
         }
@@ -126,163 +248,100 @@ impl KernelSet {
     }
 
     #[inline]
-    fn spiky_gradient(&self, delta: Vec2, distance: f32) -> Vec2 {
+    fn spiky_gradient(&self, delta: Vector, distance: Real) -> Vector {
+        delta * self.spiky_gradient_scale(distance)
+    }
+
+    #[inline]
+    fn spiky_gradient_scale(&self, distance: Real) -> Real {
         if distance <= EPSILON || distance >= self.support_radius {
-            return Vec2::ZERO;
+            return 0.0;
         }
 
-        let scale = self.spiky_grad_coeff * (self.support_radius - distance).powi(2) / distance;
-        delta * scale
+        self.spiky_grad_coeff * (self.support_radius - distance).powi(2) / distance
     }
 
     #[inline]
-    fn viscosity_laplacian(&self, distance: f32) -> f32 {
+    #[cfg_attr(all(feature = "simd", not(feature = "f64")), allow(dead_code))]
+    fn viscosity_laplacian(&self, distance: Real) -> Real {
         if distance >= self.support_radius {
             return 0.0;
         }
 
         self.viscosity_laplacian_coeff * (self.support_radius - distance)
     }
-}
-
-#[derive(Debug)]
-struct UniformGrid {
-    cell_counts: Vec<usize>,
-    cell_offsets: Vec<usize>,
-    cell_cursor: Vec<usize>,
-    sorted_particles: Vec<usize>,
-    particle_cells: Vec<usize>,
-    neighbor_cells: Vec<[usize; 9]>,
-    neighbor_counts: Vec<u8>,
-    cols: usize,
-    inv_cell_size: f32,
-    bounds_left: f32,
-    bounds_bottom: f32,
-}
-
-impl UniformGrid {
-    fn new(bounds: Rect, cell_size: f32) -> Self {
-        let cols = (bounds.w() / cell_size).ceil().max(1.0) as usize + 1;
-        let rows = (bounds.h() / cell_size).ceil().max(1.0) as usize + 1;
-        let cell_total = cols * rows;
-        let mut neighbor_cells = vec![[0; 9]; cell_total];
-        let mut neighbor_counts = vec![0; cell_total];
-
-        for row in 0..rows {
-            for col in 0..cols {
-                let cell_index = row * cols + col;
-                let min_row = row.saturating_sub(1);
-                let max_row = (row + 1).min(rows - 1);
-                let min_col = col.saturating_sub(1);
-                let max_col = (col + 1).min(cols - 1);
-                let mut count = 0;
-
-                for current_row in min_row..=max_row {
-                    for current_col in min_col..=max_col {
-                        neighbor_cells[cell_index][count] = current_row * cols + current_col;
-                        count += 1;
-                    }
-                }
-
-                neighbor_counts[cell_index] = count as u8;
-            }
-        }
 
-        Self {
-            cell_counts: vec![0; cell_total],
-            cell_offsets: vec![0; cell_total + 1],
-            cell_cursor: vec![0; cell_total],
-            sorted_particles: Vec::new(),
-            particle_cells: Vec::new(),
-            neighbor_cells,
-            neighbor_counts,
-            cols,
-            inv_cell_size: cell_size.recip(),
-            bounds_left: bounds.left(),
-            bounds_bottom: bounds.bottom(),
-        }
-    }
-
-    fn rebuild(&mut self, positions: &[Vec2]) {
-        self.cell_counts.fill(0);
-
-        if self.sorted_particles.len() != positions.len() {
-            self.sorted_particles.resize(positions.len(), 0);
-            self.particle_cells.resize(positions.len(), 0);
-        }
-
-        for (index, position) in positions.iter().copied().enumerate() {
-            let cell = self.cell_index(position);
-            self.particle_cells[index] = cell;
-            self.cell_counts[cell] += 1;
-        }
-
-        let mut offset = 0;
-        for cell in 0..self.cell_counts.len() {
-            self.cell_offsets[cell] = offset;
-            self.cell_cursor[cell] = offset;
-            offset += self.cell_counts[cell];
+    #[inline]
+    fn cohesion(&self, distance: Real) -> Real {
+        if distance >= self.support_radius {
+            return 0.0;
         }
-        self.cell_offsets[self.cell_counts.len()] = offset;
 
-        for particle_index in 0..positions.len() {
-            let cell = self.particle_cells[particle_index];
-            let slot = self.cell_cursor[cell];
-            self.sorted_particles[slot] = particle_index;
-            self.cell_cursor[cell] = slot + 1;
+        let h = self.support_radius;
+        let spline = (h - distance).powi(3) * distance.powi(3);
+        if 2.0 * distance > h {
+            self.cohesion_coeff * spline
+        } else {
+            self.cohesion_coeff * (2.0 * spline - h.powi(6) / 64.0)
         }
     }
 
     #[inline]
-    fn for_each_neighbor<F>(&self, particle_cell: usize, mut visit: F)
-    where
-        F: FnMut(usize),
-    {
-        let cells = &self.neighbor_cells[particle_cell];
-        let count = self.neighbor_counts[particle_cell] as usize;
-
-        for offset in 0..count {
-            let cell = cells[offset];
-            let start = self.cell_offsets[cell];
-            let end = self.cell_offsets[cell + 1];
-
-            for slot in start..end {
-                visit(self.sorted_particles[slot]);
-            }
+    fn adhesion(&self, distance: Real) -> Real {
+        let h = self.support_radius;
+        if distance >= h || 2.0 * distance <= h {
+            return 0.0;
         }
-    }
-
-    #[inline]
-    fn cell_index(&self, position: Vec2) -> usize {
-        let col = ((position.x - self.bounds_left) * self.inv_cell_size).floor() as isize;
-        let row = ((position.y - self.bounds_bottom) * self.inv_cell_size).floor() as isize;
-        let col = col.clamp(0, self.cols as isize - 1) as usize;
-        let row = row.clamp(0, self.cell_counts.len() as isize / self.cols as isize - 1) as usize;
 
-        row * self.cols + col
+        self.adhesion_coeff * (-4.0 * distance * distance / h + 6.0 * distance - 2.0 * h).powf(0.25)
     }
 }
 
+#[derive(Clone)]
 pub struct SphSimulation {
     config: SphConfig,
     kernels: KernelSet,
-    positions: Vec<Vec2>,
-    velocities: Vec<Vec2>,
-    densities: Vec<f32>,
-    inv_densities: Vec<f32>,
-    pressures: Vec<f32>,
-    pressure_terms: Vec<f32>,
-    accelerations: Vec<Vec2>,
-    xsph_corrections: Vec<Vec2>,
-    grid: UniformGrid,
+    positions: Vec<Vector>,
+    velocities: Vec<Vector>,
+    densities: Vec<Real>,
+    density_rates: Vec<Real>,
+    density_gradients: Vec<Vector>,
+    inv_densities: Vec<Real>,
+    pressures: Vec<Real>,
+    pressure_terms: Vec<Real>,
+    phases: Vec<Phase>,
+    deviatoric_stresses: Vec<SymmetricMatrix2>,
+    pinned: Vec<bool>,
+    solids: SolidState,
+    porous_zones: Vec<PorousZone>,
+    porous_indices: Vec<Option<usize>>,
+    gradient_corrections: Vec<Matrix2>,
+    accelerations: Vec<Vector>,
+    xsph_corrections: Vec<Vector>,
+    shifts: Vec<Vector>,
+    dissipative_terms: Vec<DissipativeTerms>,
+    classes: Vec<ParticleClass>,
+    surface_normals: Vec<Vector>,
+    vorticities: Vec<Real>,
+    particle_ids: Vec<u32>,
+    id_slots: Vec<u32>,
+    grid: NeighborGrid,
     stats: SimulationStats,
     stats_dirty: bool,
+    neighbors_stale: bool,
+    classes_stale: bool,
+    vorticities_stale: bool,
+    step_count: u64,
+    phase_timings: PhaseTimings,
+    reference_volume: Real,
+    step_dissipation: DissipationBreakdown,
+    total_dissipation: DissipationBreakdown,
 }
 
 impl SphSimulation {
     pub fn new(config: SphConfig) -> Self {
         let kernels = KernelSet::new(config.smoothing_radius);
-        let grid = UniformGrid::new(config.bounds, config.smoothing_radius);
+        let grid = NeighborGrid::new(&config);
 
         Self {
             config,
@@ -290,64 +349,209 @@ impl SphSimulation {
             positions: Vec::new(),
             velocities: Vec::new(),
             densities: Vec::new(),
+            density_rates: Vec::new(),
+            density_gradients: Vec::new(),
             inv_densities: Vec::new(),
             pressures: Vec::new(),
             pressure_terms: Vec::new(),
+            phases: Vec::new(),
+            deviatoric_stresses: Vec::new(),
+            pinned: Vec::new(),
+            solids: SolidState::default(),
+            porous_zones: Vec::new(),
+            porous_indices: Vec::new(),
+            gradient_corrections: Vec::new(),
             accelerations: Vec::new(),
             xsph_corrections: Vec::new(),
+            shifts: Vec::new(),
+            dissipative_terms: Vec::new(),
+            classes: Vec::new(),
+            surface_normals: Vec::new(),
+            vorticities: Vec::new(),
+            particle_ids: Vec::new(),
+            id_slots: Vec::new(),
             grid,
             stats: SimulationStats {
                 threads: rayon::current_num_threads(),
                 ..SimulationStats::default()
             },
             stats_dirty: true,
+            neighbors_stale: false,
+            classes_stale: false,
+            vorticities_stale: false,
+            step_count: 0,
+            phase_timings: PhaseTimings::default(),
+            reference_volume: 0.0,
+            step_dissipation: DissipationBreakdown::default(),
+            total_dissipation: DissipationBreakdown::default(),
         }
     }
 
-    pub fn seed_block(&mut self, cols: usize, rows: usize, origin: Vec2) {
+    pub fn seed_block(&mut self, cols: usize, rows: usize, origin: Vector) {
+        self.seed_block_with_phase(cols, rows, origin, Phase::Fluid);
+    }
+
+    pub fn seed_block_with_phase(
+        &mut self,
+        cols: usize,
+        rows: usize,
+        origin: Vector,
+        phase: Phase,
+    ) {
         let additional = cols * rows;
+        let first = self.positions.len();
         self.positions.reserve(additional);
         self.velocities.reserve(additional);
         self.densities.reserve(additional);
+        self.density_rates.reserve(additional);
+        self.density_gradients.reserve(additional);
         self.inv_densities.reserve(additional);
         self.pressures.reserve(additional);
         self.pressure_terms.reserve(additional);
+        self.phases.reserve(additional);
+        self.deviatoric_stresses.reserve(additional);
+        self.pinned.reserve(additional);
+        self.porous_indices.reserve(additional);
+        self.gradient_corrections.reserve(additional);
         self.accelerations.reserve(additional);
         self.xsph_corrections.reserve(additional);
+        self.shifts.reserve(additional);
+        self.dissipative_terms.reserve(additional);
+        self.classes.reserve(additional);
+        self.surface_normals.reserve(additional);
+        self.vorticities.reserve(additional);
+        self.particle_ids.reserve(additional);
+        self.id_slots.reserve(additional);
 
         for row in 0..rows {
             for col in 0..cols {
-                let position = origin
-                    + vec2(
-                        col as f32 * self.config.particle_spacing,
-                        row as f32 * self.config.particle_spacing,
+                let id = self.particle_ids.len() as u32;
+                let mut position = origin
+                    + Vector::new(
+                        col as Real * self.config.particle_spacing,
+                        row as Real * self.config.particle_spacing,
                     );
+                if self.config.seed_jitter > 0.0 {
+                    let mut rng = self.particle_rng(id, determinism::SEED_JITTER_STREAM);
+                    let amplitude = self.config.seed_jitter * self.config.particle_spacing;
+                    position += amplitude
+                        * Vector::new(2.0 * rng.next_real() - 1.0, 2.0 * rng.next_real() - 1.0);
+                }
 
                 self.positions.push(position);
-                self.velocities.push(Vec2::ZERO);
+                self.velocities.push(Vector::ZERO);
                 self.densities.push(self.config.rest_density);
+                self.density_rates.push(0.0);
+                self.density_gradients.push(Vector::ZERO);
                 self.inv_densities.push(self.config.rest_density.recip());
                 self.pressures.push(0.0);
                 self.pressure_terms.push(0.0);
-                self.accelerations.push(Vec2::ZERO);
-                self.xsph_corrections.push(Vec2::ZERO);
+                self.phases.push(phase);
+                self.deviatoric_stresses.push(SymmetricMatrix2::ZERO);
+                self.pinned.push(false);
+                self.porous_indices.push(None);
+                self.gradient_corrections.push(Matrix2::IDENTITY);
+                self.accelerations.push(Vector::ZERO);
+                self.xsph_corrections.push(Vector::ZERO);
+                self.shifts.push(Vector::ZERO);
+                self.dissipative_terms.push(DissipativeTerms::default());
+                self.classes.push(ParticleClass::default());
+                self.surface_normals.push(Vector::ZERO);
+                self.vorticities.push(0.0);
+                self.particle_ids.push(id);
+                self.id_slots.push(id);
             }
         }
 
+        if phase == Phase::Solid {
+            self.attach_solid_body(first, additional);
+        }
+
+        self.reference_volume +=
+            additional as Real * self.config.particle_mass / self.config.rest_density;
+
+        self.neighbors_stale = true;
+        self.classes_stale = true;
+        self.vorticities_stale = true;
         self.stats_dirty = true;
         self.refresh_stats();
     }
 
-    pub fn step(&mut self, dt: f32, interaction: Option<Interaction>) {
+    #[doc(hidden)]
+    pub fn rebuild_neighbors(&mut self) {
+        self.grid.rebuild(&self.positions);
+        self.neighbors_stale = false;
+    }
+
+    fn refresh_neighbors(&mut self) {
+        if self.neighbors_stale {
+            self.rebuild_neighbors();
+        }
+    }
+
+    pub fn step(&mut self, dt: Real, interaction: Option<Interaction>) {
         if self.positions.is_empty() {
             return;
         }
 
-        self.grid.rebuild(&self.positions);
-        self.compute_densities();
+        let mut timer = PhaseTimer::start();
+        let mut timings = PhaseTimings::default();
+
+        if self.config.reorder_interval > 0
+            && self
+                .step_count
+                .is_multiple_of(self.config.reorder_interval as u64)
+        {
+            self.reorder_particles();
+        }
+        timings.reorder = timer.lap();
+        self.rebuild_neighbors();
+        timings.neighbors = timer.lap();
+        let continuity = self.config.density_method == DensityMethod::Continuity;
+        if continuity && self.config.kernel_gradient_correction {
+            // The continuity equation runs before pressures, so its corrections use the densities
+            // carried over from the previous step.
+            self.compute_gradient_corrections();
+        }
+        match self.config.density_method {
+            DensityMethod::Summation => self.compute_densities(),
+            DensityMethod::Continuity => self.evolve_densities(dt),
+        }
+        timings.density = timer.lap();
+        if !self.porous_zones.is_empty() {
+            self.locate_porous_zones();
+        }
+        timings.porous = timer.lap();
         self.compute_pressures();
+        timings.pressure = timer.lap();
+        if self.config.kernel_gradient_correction && !continuity {
+            self.compute_gradient_corrections();
+        }
+        timings.correction = timer.lap();
+        self.update_granular_stresses(dt);
+        timings.granular = timer.lap();
+        if self.classification_needed() {
+            self.classify_particles();
+        } else {
+            self.classes_stale = true;
+        }
+        timings.classification = timer.lap();
+        if self.config.vorticity_confinement > 0.0 {
+            self.compute_vorticities();
+        } else {
+            self.vorticities_stale = true;
+        }
+        timings.vorticity = timer.lap();
         self.compute_accelerations(interaction);
+        timings.accelerations = timer.lap();
+        self.apply_solid_forces();
+        timings.solids = timer.lap();
         self.integrate(dt);
+        timings.integrate = timer.lap();
+
+        self.phase_timings.blend(timings, self.step_count == 0);
+        self.step_count += 1;
+        self.neighbors_stale = true;
         self.stats_dirty = true;
     }
 
@@ -355,18 +559,22 @@ impl SphSimulation {
         &self.config
     }
 
-    pub fn positions(&self) -> &[Vec2] {
+    pub fn positions(&self) -> &[Vector] {
         &self.positions
     }
 
-    pub fn velocities(&self) -> &[Vec2] {
+    pub fn velocities(&self) -> &[Vector] {
         &self.velocities
     }
 
-    pub fn densities(&self) -> &[f32] {
+    pub fn densities(&self) -> &[Real] {
         &self.densities
     }
 
+    pub fn pressures(&self) -> &[Real] {
+        &self.pressures
+    }
+
     pub fn stats(&self) -> SimulationStats {
         self.stats
     }
@@ -376,28 +584,31 @@ impl SphSimulation {
             return;
         }
 
+        self.refresh_classes();
         self.stats = SimulationStats {
             particle_count: self.positions.len(),
-            max_speed: self
-                .velocities
-                .par_iter()
+            max_speed: (0..self.positions.len())
+                .into_par_iter()
                 .with_min_len(MIN_PAR_CHUNK)
-                .map(|velocity| velocity.length())
-                .reduce(|| 0.0, f32::max),
+                .map(|index| self.reported_velocity(index).length())
+                .reduce(|| 0.0, Real::max),
             max_density_ratio: self
                 .densities
                 .par_iter()
                 .with_min_len(MIN_PAR_CHUNK)
                 .map(|density| density / self.config.rest_density)
-                .reduce(|| 0.0, f32::max),
+                .reduce(|| 0.0, Real::max),
             threads: rayon::current_num_threads(),
+            energy: self.energy_report(),
+            contact_angles: self.measured_contact_angles(),
+            timings: self.phase_timings,
         };
         self.stats_dirty = false;
     }
 
-    fn compute_densities(&mut self) {
+    #[doc(hidden)]
+    pub fn compute_densities(&mut self) {
         let positions = &self.positions;
-        let particle_cells = &self.grid.particle_cells;
         let grid = &self.grid;
         let kernels = self.kernels;
         let particle_mass = self.config.particle_mass;
@@ -410,116 +621,240 @@ impl SphSimulation {
             .for_each(|(index, density)| {
                 let position_i = positions[index];
                 let mut value = 0.0;
+                let mut batch = NeighborBatch::new();
+                let mut accumulate = |pair: PairKernel| value += particle_mass * pair.poly6;
 
-                grid.for_each_neighbor(particle_cells[index], |neighbor| {
-                    let delta = position_i - positions[neighbor];
-                    value += particle_mass * kernels.poly6(delta.length_squared());
+                grid.for_each_neighbor(index, |neighbor| {
+                    if batch.push(neighbor, position_i - positions[neighbor], Vector::ZERO) {
+                        batch.flush(&kernels, &mut accumulate);
+                    }
                 });
+                batch.flush(&kernels, &mut accumulate);
 
                 *density = value.max(minimum_density);
             });
     }
 
-    fn compute_pressures(&mut self) {
+    #[doc(hidden)]
+    pub fn compute_pressures(&mut self) {
         let rest_density = self.config.rest_density;
         let pressure_stiffness = self.config.pressure_stiffness;
         let gamma = self.config.gamma;
         let max_density_ratio = self.config.max_density_ratio;
+        let porous_zones = &self.porous_zones;
 
         self.pressures
             .par_iter_mut()
             .zip(self.inv_densities.par_iter_mut())
             .zip(self.pressure_terms.par_iter_mut())
             .zip(self.densities.par_iter().copied())
+            .zip(self.porous_indices.par_iter().copied())
             .with_min_len(MIN_PAR_CHUNK)
-            .for_each(|(((pressure, inv_density), pressure_term), density)| {
-                let clamped_density = density.max(EPSILON);
-                let density_inv = clamped_density.recip();
-                let ratio = (clamped_density / rest_density).clamp(1.0, max_density_ratio);
-                let value = pressure_stiffness * (ratio.powf(gamma) - 1.0);
-
-                *pressure = value;
-                *inv_density = density_inv;
-                *pressure_term = value * density_inv * density_inv;
-            });
+            .for_each(
+                |((((pressure, inv_density), pressure_term), density), zone)| {
+                    let clamped_density = density.max(EPSILON);
+                    let density_inv = clamped_density.recip();
+                    let intrinsic_density =
+                        Self::intrinsic_density(porous_zones, zone, clamped_density);
+                    let ratio = (intrinsic_density / rest_density).clamp(1.0, max_density_ratio);
+                    let value = pressure_stiffness * (ratio.powf(gamma) - 1.0);
+
+                    *pressure = value;
+                    *inv_density = density_inv;
+                    *pressure_term = value * density_inv * density_inv;
+                },
+            );
     }
 
-    fn compute_accelerations(&mut self, interaction: Option<Interaction>) {
+    #[doc(hidden)]
+    pub fn compute_accelerations(&mut self, interaction: Option<Interaction>) {
         let config = self.config;
         let kernels = self.kernels;
         let positions = &self.positions;
         let velocities = &self.velocities;
         let inv_densities = &self.inv_densities;
         let pressure_terms = &self.pressure_terms;
-        let particle_cells = &self.grid.particle_cells;
+        let vorticities = &self.vorticities;
+        let surface_normals = &self.surface_normals;
+        let phases = &self.phases;
+        let deviatoric_stresses = &self.deviatoric_stresses;
+        let porous_zones = &self.porous_zones;
+        let porous_indices = &self.porous_indices;
+        let gradient_corrections = &self.gradient_corrections;
         let grid = &self.grid;
         let surface_threshold_sq = config.surface_threshold * config.surface_threshold;
+        let sound_speed = config.sound_speed();
+        let artificial_viscosity =
+            config.artificial_viscosity_alpha > 0.0 || config.artificial_viscosity_beta > 0.0;
+        let viscosity_softening = 0.01 * config.smoothing_radius * config.smoothing_radius;
+        let akinci = config.surface_tension_model == SurfaceTensionModel::Akinci;
+        let wetting = config
+            .wall_materials
+            .iter()
+            .any(|material| material.contact_angle.is_some());
 
         self.accelerations
             .par_iter_mut()
             .zip(self.xsph_corrections.par_iter_mut())
+            .zip(self.dissipative_terms.par_iter_mut())
             .enumerate()
             .with_min_len(MIN_PAR_CHUNK)
-            .for_each(|(index, (acceleration, xsph))| {
+            .for_each(|(index, ((acceleration, xsph), dissipative))| {
                 let position_i = positions[index];
                 let velocity_i = velocities[index];
                 let density_inv_i = inv_densities[index];
                 let pressure_term_i = pressure_terms[index];
-
-                let mut pressure_force = Vec2::ZERO;
-                let mut viscosity_force = Vec2::ZERO;
-                let mut surface_normal = Vec2::ZERO;
+                let vorticity_i = vorticities[index];
+                let fluid_i = phases[index] == Phase::Fluid;
+                let solid_i = phases[index] == Phase::Solid;
+                let stress_term_i =
+                    deviatoric_stresses[index].scaled(density_inv_i * density_inv_i);
+
+                let mut pressure_force = Vector::ZERO;
+                let mut viscosity_force = Vector::ZERO;
+                let mut artificial_viscosity_force = Vector::ZERO;
+                let mut tension_force = Vector::ZERO;
+                let mut stress_force = Vector::ZERO;
+                let mut surface_normal = Vector::ZERO;
                 let mut color_laplacian = 0.0;
-                let mut velocity_blend = Vec2::ZERO;
-
-                grid.for_each_neighbor(particle_cells[index], |neighbor| {
-                    if index == neighbor {
-                        return;
-                    }
-
-                    let delta = position_i - positions[neighbor];
-                    let distance_sq = delta.length_squared();
-
-                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
-                        return;
-                    }
-
-                    let distance = distance_sq.sqrt();
+                let mut velocity_blend = Vector::ZERO;
+                let mut vorticity_gradient = Vector::ZERO;
+
+                let mut interact = |pair: PairKernel| {
+                    let PairKernel {
+                        neighbor,
+                        delta,
+                        relative_velocity,
+                        distance_sq,
+                        distance,
+                        poly6,
+                        gradient: kernel_gradient,
+                        laplacian,
+                    } = pair;
                     let density_inv_j = inv_densities[neighbor];
                     let pressure_term_j = pressure_terms[neighbor];
-                    let velocity_j = velocities[neighbor];
-                    let gradient = kernels.spiky_gradient(delta, distance);
-                    let laplacian = kernels.viscosity_laplacian(distance);
+                    let gradient = if config.kernel_gradient_correction {
+                        symmetric_correction(gradient_corrections, index, neighbor, kernel_gradient)
+                    } else {
+                        kernel_gradient
+                    };
                     let mass_density_j = config.particle_mass * density_inv_j;
 
                     pressure_force -=
                         config.particle_mass * (pressure_term_i + pressure_term_j) * gradient;
-                    viscosity_force +=
-                        config.viscosity * mass_density_j * (velocity_j - velocity_i) * laplacian;
-                    surface_normal += mass_density_j * gradient;
+
+                    let fluid_j = phases[neighbor] == Phase::Fluid;
+                    if fluid_i || fluid_j {
+                        viscosity_force +=
+                            config.viscosity * mass_density_j * relative_velocity * laplacian;
+                    }
+                    if !fluid_i || !fluid_j {
+                        let stress_term_j =
+                            deviatoric_stresses[neighbor].scaled(density_inv_j * density_inv_j);
+                        stress_force += config.particle_mass
+                            * (stress_term_i + stress_term_j).transform(gradient);
+                    }
+
+                    let approach = -relative_velocity.dot(delta);
+                    if artificial_viscosity && approach < 0.0 {
+                        let mu = config.smoothing_radius * approach
+                            / (distance_sq + viscosity_softening);
+                        let mean_density = 0.5 * (density_inv_i.recip() + density_inv_j.recip());
+                        let pi = (-config.artificial_viscosity_alpha * sound_speed * mu
+                            + config.artificial_viscosity_beta * mu * mu)
+                            / mean_density;
+                        artificial_viscosity_force -= config.particle_mass * pi * gradient;
+                    }
+                    if akinci {
+                        let density_ratio =
+                            2.0 * config.rest_density * density_inv_i * density_inv_j
+                                / (density_inv_i + density_inv_j);
+                        tension_force += Self::akinci_tension(
+                            config,
+                            kernels,
+                            delta,
+                            distance,
+                            density_ratio,
+                            surface_normals[index] - surface_normals[neighbor],
+                        );
+                    }
+                    surface_normal += mass_density_j * kernel_gradient;
                     color_laplacian += mass_density_j * laplacian;
+                    vorticity_gradient += mass_density_j
+                        * (vorticities[neighbor].abs() - vorticity_i.abs())
+                        * gradient;
 
                     let inv_average_density = 2.0 * density_inv_i * density_inv_j
                         / (density_inv_i + density_inv_j).max(EPSILON);
                     velocity_blend += config.xsph_factor
                         * config.particle_mass
-                        * (velocity_j - velocity_i)
-                        * kernels.poly6(distance_sq)
+                        * relative_velocity
+                        * poly6
                         * inv_average_density;
+                };
+
+                let mut batch = NeighborBatch::new();
+                grid.for_each_neighbor(index, |neighbor| {
+                    if index == neighbor {
+                        return;
+                    }
+
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    if solid_i && phases[neighbor] == Phase::Solid {
+                        return;
+                    }
+
+                    if batch.push(neighbor, delta, velocities[neighbor] - velocity_i) {
+                        batch.flush(&kernels, &mut interact);
+                    }
                 });
+                batch.flush(&kernels, &mut interact);
 
+                let (boundary_repulsion, boundary_damping) =
+                    Self::boundary_acceleration(config, position_i, velocity_i);
+                let porous_drag =
+                    Self::porous_drag(config, porous_zones, porous_indices[index], velocity_i);
                 let mut total_acceleration = config.gravity
+                    + Self::frame_acceleration(config, position_i, velocity_i)
                     + pressure_force
                     + viscosity_force
-                    + Self::boundary_acceleration(config, position_i, velocity_i);
-
+                    + artificial_viscosity_force
+                    + stress_force
+                    + porous_drag
+                    + boundary_repulsion
+                    + boundary_damping;
+
+                let surface_normal = if wetting {
+                    Self::wetting_normal(config, position_i, surface_normal)
+                } else {
+                    surface_normal
+                };
                 let normal_sq = surface_normal.length_squared();
-                if normal_sq > surface_threshold_sq {
+                if !fluid_i {
+                    // Granular particles carry their own stress instead of surface tension.
+                } else if akinci {
+                    total_acceleration +=
+                        tension_force + Self::wall_adhesion(config, kernels, position_i);
+                } else if normal_sq > surface_threshold_sq {
                     total_acceleration +=
                         -config.surface_tension * color_laplacian * surface_normal
                             / normal_sq.sqrt();
                 }
 
+                if config.vorticity_confinement > 0.0 {
+                    total_acceleration += Self::confinement_acceleration(
+                        config.vorticity_confinement,
+                        vorticity_i,
+                        vorticity_gradient,
+                    );
+                }
+
                 if let Some(current_interaction) = interaction {
                     let density_feedback = (config.rest_density * density_inv_i).clamp(0.35, 1.0);
                     total_acceleration +=
@@ -527,49 +862,106 @@ impl SphSimulation {
                             * density_feedback;
                 }
 
-                *acceleration = Self::clamp_magnitude(total_acceleration, config.max_acceleration);
-                *xsph = velocity_blend;
+                *acceleration = clamp_magnitude(total_acceleration, config.max_acceleration);
+                *xsph = if solid_i {
+                    Vector::ZERO
+                } else {
+                    velocity_blend
+                };
+                *dissipative = DissipativeTerms {
+                    viscosity: viscosity_force,
+                    artificial_viscosity: artificial_viscosity_force,
+                    porous_drag,
+                    boundary_damping,
+                };
             });
     }
 
-    fn integrate(&mut self, dt: f32) {
+    #[doc(hidden)]
+    pub fn integrate(&mut self, dt: Real) {
         let config = self.config;
-
-        self.positions
-            .par_iter_mut()
-            .zip(self.velocities.par_iter_mut())
-            .zip(self.accelerations.par_iter().copied())
-            .zip(self.xsph_corrections.par_iter().copied())
-            .with_min_len(MIN_PAR_CHUNK)
-            .for_each(|(((position, velocity), acceleration), xsph)| {
-                let xsph = Self::clamp_magnitude(xsph, config.xsph_max_velocity);
-                *velocity += acceleration * dt;
-                *velocity = Self::clamp_magnitude(*velocity, config.max_velocity);
-                *velocity *= config.velocity_damping;
-                *position += (*velocity + xsph) * dt;
-
-                if !position.x.is_finite() || !position.y.is_finite() {
-                    *position = config.bounds.xy();
-                }
-                if !velocity.x.is_finite() || !velocity.y.is_finite() {
-                    *velocity = Vec2::ZERO;
-                }
-
-                Self::resolve_boundaries(config, position, velocity);
-            });
+        let particle_mass = config.particle_mass;
+
+        let dissipation = determinism::reduce(
+            self.positions
+                .par_iter_mut()
+                .zip(self.velocities.par_iter_mut())
+                .zip(self.accelerations.par_iter().copied())
+                .zip(self.xsph_corrections.par_iter().copied())
+                .zip(self.dissipative_terms.par_iter().copied())
+                .zip(self.pinned.par_iter().copied())
+                .with_min_len(MIN_PAR_CHUNK)
+                .map(
+                    |(((((position, velocity), acceleration), xsph), dissipative), pinned)| {
+                        if pinned {
+                            *velocity = Vector::ZERO;
+                            return DissipationBreakdown::default();
+                        }
+
+                        let work = |term: Vector| -particle_mass * velocity.dot(term) * dt;
+                        let mut dissipation = DissipationBreakdown {
+                            viscosity: work(dissipative.viscosity),
+                            artificial_viscosity: work(dissipative.artificial_viscosity),
+                            porous: work(dissipative.porous_drag),
+                            boundary: work(dissipative.boundary_damping),
+                            ..DissipationBreakdown::default()
+                        };
+
+                        let xsph = clamp_magnitude(xsph, config.xsph_max_velocity);
+                        *velocity += acceleration * dt;
+                        let unclamped_energy = kinetic_energy(particle_mass, *velocity);
+                        *velocity = clamp_magnitude(*velocity, config.max_velocity);
+                        let clamped_energy = kinetic_energy(particle_mass, *velocity);
+                        *velocity *= config.velocity_damping;
+                        let damped_energy = kinetic_energy(particle_mass, *velocity);
+                        *position += (*velocity + xsph) * dt;
+
+                        dissipation.velocity_clamp = unclamped_energy - clamped_energy;
+                        dissipation.velocity_damping = clamped_energy - damped_energy;
+                        // Kinetic energy removed by XSPH smoothing, not an exact dissipation.
+                        dissipation.xsph =
+                            damped_energy - kinetic_energy(particle_mass, *velocity + xsph);
+
+                        if !position.x.is_finite() || !position.y.is_finite() {
+                            *position = Vector::new(config.bounds.x(), config.bounds.y());
+                        }
+                        if !velocity.x.is_finite() || !velocity.y.is_finite() {
+                            *velocity = Vector::ZERO;
+                            dissipation = DissipationBreakdown::default();
+                        }
+
+                        let unresolved_energy = kinetic_energy(particle_mass, *velocity);
+                        Self::resolve_boundaries(config, position, velocity);
+                        dissipation.boundary +=
+                            unresolved_energy - kinetic_energy(particle_mass, *velocity);
+
+                        dissipation
+                    },
+                ),
+            config.deterministic,
+            DissipationBreakdown::default,
+            |total, particle| total + particle,
+        );
+
+        self.step_dissipation = dissipation;
+        self.total_dissipation = self.total_dissipation + dissipation;
+
+        if config.shifting_coefficient > 0.0 {
+            self.shift_particles(dt);
+        }
     }
 
     fn interaction_acceleration(
         config: SphConfig,
-        position: Vec2,
+        position: Vector,
         interaction: Interaction,
-    ) -> Vec2 {
+    ) -> Vector {
         let delta = interaction.position - position;
         let distance_sq = delta.length_squared();
         let radius_sq = interaction.radius * interaction.radius;
 
         if distance_sq <= EPSILON || distance_sq >= radius_sq {
-            return Vec2::ZERO;
+            return Vector::ZERO;
         }
 
         let distance = distance_sq.sqrt();
@@ -580,93 +972,47 @@ impl SphSimulation {
             InteractionMode::Repel => -direction * interaction.strength * falloff,
         };
 
-        Self::clamp_magnitude(acceleration, config.interaction_max_acceleration)
+        clamp_magnitude(acceleration, config.interaction_max_acceleration)
     }
 
-    fn boundary_acceleration(config: SphConfig, position: Vec2, velocity: Vec2) -> Vec2 {
-        let mut acceleration = Vec2::ZERO;
-        let inv_support = config.smoothing_radius.recip();
-
-        let left_distance = position.x - config.bounds.left();
-        if left_distance < config.smoothing_radius {
-            let weight = 1.0 - left_distance * inv_support;
-            acceleration.x += config.boundary_stiffness * weight * weight;
-            if velocity.x < 0.0 {
-                acceleration.x += -config.boundary_damping * velocity.x;
-            }
-        }
-
-        let right_distance = config.bounds.right() - position.x;
-        if right_distance < config.smoothing_radius {
-            let weight = 1.0 - right_distance * inv_support;
-            acceleration.x -= config.boundary_stiffness * weight * weight;
-            if velocity.x > 0.0 {
-                acceleration.x -= config.boundary_damping * velocity.x;
-            }
-        }
-
-        let bottom_distance = position.y - config.bounds.bottom();
-        if bottom_distance < config.smoothing_radius {
-            let weight = 1.0 - bottom_distance * inv_support;
-            acceleration.y += config.boundary_stiffness * weight * weight;
-            if velocity.y < 0.0 {
-                acceleration.y += -config.boundary_damping * velocity.y;
-            }
-        }
-
-        let top_distance = config.bounds.top() - position.y;
-        if top_distance < config.smoothing_radius {
-            let weight = 1.0 - top_distance * inv_support;
-            acceleration.y -= config.boundary_stiffness * weight * weight;
-            if velocity.y > 0.0 {
-                acceleration.y -= config.boundary_damping * velocity.y;
-            }
-        }
-
-        acceleration
-    }
-
-    fn resolve_boundaries(config: SphConfig, position: &mut Vec2, velocity: &mut Vec2) {
-        let min_x = config.bounds.left() + config.boundary_margin;
-        let max_x = config.bounds.right() - config.boundary_margin;
-        let min_y = config.bounds.bottom() + config.boundary_margin;
-        let max_y = config.bounds.top() - config.boundary_margin;
-
-        if position.x < min_x {
-            position.x = min_x;
-            if velocity.x < 0.0 {
-                velocity.x *= -config.boundary_restitution;
-            }
-        } else if position.x > max_x {
-            position.x = max_x;
-            if velocity.x > 0.0 {
-                velocity.x *= -config.boundary_restitution;
-            }
-        }
-
-        if position.y < min_y {
-            position.y = min_y;
-            if velocity.y < 0.0 {
-                velocity.y *= -config.boundary_restitution;
-            }
-        } else if position.y > max_y {
-            position.y = max_y;
-            if velocity.y > 0.0 {
-                velocity.y *= -config.boundary_restitution;
-            }
-        }
+    fn boundary_acceleration(
+        config: SphConfig,
+        position: Vector,
+        velocity: Vector,
+    ) -> (Vector, Vector) {
+        let walls = config.wall_response();
+        let (repulsion_x, damping_x) = walls.acceleration(
+            position.x - config.bounds.left(),
+            config.bounds.right() - position.x,
+            velocity.x,
+        );
+        let (repulsion_y, damping_y) = walls.acceleration(
+            position.y - config.bounds.bottom(),
+            config.bounds.top() - position.y,
+            velocity.y,
+        );
+
+        (
+            Vector::new(repulsion_x, repulsion_y),
+            Vector::new(damping_x, damping_y),
+        )
     }
 
-    #[inline]
-    fn clamp_magnitude(vector: Vec2, max_length: f32) -> Vec2 {
-        let length_sq = vector.length_squared();
-        let max_sq = max_length * max_length;
-
-        if length_sq > max_sq && length_sq > EPSILON {
-            vector * (max_length / length_sq.sqrt())
-        } else {
-            vector
-        }
+    fn resolve_boundaries(config: SphConfig, position: &mut Vector, velocity: &mut Vector) {
+        let walls = config.wall_response();
+
+        walls.resolve(
+            &mut position.x,
+            &mut velocity.x,
+            config.bounds.left() + config.boundary_margin,
+            config.bounds.right() - config.boundary_margin,
+        );
+        walls.resolve(
+            &mut position.y,
+            &mut velocity.y,
+            config.bounds.bottom() + config.boundary_margin,
+            config.bounds.top() - config.boundary_margin,
+        );
     }
 }
 
@@ -678,22 +1024,28 @@ mod tests {
     fn poly6_is_zero_outside_support() {
         let kernels = KernelSet::new(0.1);
 
-        assert_eq!(kernels.poly6(0.11f32.powi(2)), 0.0);
+        assert_eq!(kernels.poly6(Real::powi(0.11, 2)), 0.0);
     }
 
     #[test]
-    fn grid_queries_only_local_cells() {
-        let bounds = Rect::from_w_h(1.0, 1.0);
-        let mut grid = UniformGrid::new(bounds, 0.1);
-        let positions = vec![vec2(-0.25, -0.25), vec2(-0.21, -0.21), vec2(0.35, 0.35)];
-
-        grid.rebuild(&positions);
-
-        let mut hits = Vec::new();
-        grid.for_each_neighbor(grid.particle_cells[0], |index| hits.push(index));
-
-        assert!(hits.contains(&0));
-        assert!(hits.contains(&1));
-        assert!(!hits.contains(&2));
+    fn artificial_viscosity_only_resists_approach() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.viscosity = 0.0;
+        config.artificial_viscosity_alpha = 0.1;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(2, 1, Vector::new(0.0, 0.0));
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+
+        for (direction, resisted) in [(1.0, true), (-1.0, false)] {
+            simulation.velocities[0] = Vector::new(direction, 0.0);
+            simulation.velocities[1] = Vector::new(-direction, 0.0);
+            simulation.compute_accelerations(None);
+
+            let term = simulation.dissipative_terms[0].artificial_viscosity;
+            assert_eq!(term.x < 0.0, resisted);
+            assert!(term.y.abs() < EPSILON);
+        }
     }
 }
@@ -0,0 +1,109 @@
+use super::{EPSILON, Real, Sph3dConfig, SphConfig, Vector, Vector3};
+use std::ops::Mul;
+
+pub(super) trait Magnitude: Copy + Mul<Real, Output = Self> {
+    fn magnitude_sq(self) -> Real;
+}
+
+impl Magnitude for Vector {
+    #[inline]
+    fn magnitude_sq(self) -> Real {
+        self.length_squared()
+    }
+}
+
+impl Magnitude for Vector3 {
+    #[inline]
+    fn magnitude_sq(self) -> Real {
+        self.length_squared()
+    }
+}
+
+#[inline]
+pub(super) fn clamp_magnitude<V: Magnitude>(vector: V, max_length: Real) -> V {
+    let length_sq = vector.magnitude_sq();
+    let max_sq = max_length * max_length;
+
+    if length_sq > max_sq && length_sq > EPSILON {
+        vector * (max_length / length_sq.sqrt())
+    } else {
+        vector
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct WallResponse {
+    support: Real,
+    stiffness: Real,
+    damping: Real,
+    restitution: Real,
+}
+
+impl WallResponse {
+    #[inline]
+    pub(super) fn acceleration(
+        &self,
+        low_distance: Real,
+        high_distance: Real,
+        velocity: Real,
+    ) -> (Real, Real) {
+        let inv_support = self.support.recip();
+        let mut repulsion = 0.0;
+        let mut damping = 0.0;
+
+        if low_distance < self.support {
+            let weight = 1.0 - low_distance * inv_support;
+            repulsion += self.stiffness * weight * weight;
+            if velocity < 0.0 {
+                damping -= self.damping * velocity;
+            }
+        }
+
+        if high_distance < self.support {
+            let weight = 1.0 - high_distance * inv_support;
+            repulsion -= self.stiffness * weight * weight;
+            if velocity > 0.0 {
+                damping -= self.damping * velocity;
+            }
+        }
+
+        (repulsion, damping)
+    }
+
+    #[inline]
+    pub(super) fn resolve(&self, position: &mut Real, velocity: &mut Real, min: Real, max: Real) {
+        if *position < min {
+            *position = min;
+            if *velocity < 0.0 {
+                *velocity *= -self.restitution;
+            }
+        } else if *position > max {
+            *position = max;
+            if *velocity > 0.0 {
+                *velocity *= -self.restitution;
+            }
+        }
+    }
+}
+
+impl SphConfig {
+    pub(super) fn wall_response(&self) -> WallResponse {
+        WallResponse {
+            support: self.smoothing_radius,
+            stiffness: self.boundary_stiffness,
+            damping: self.boundary_damping,
+            restitution: self.boundary_restitution,
+        }
+    }
+}
+
+impl Sph3dConfig {
+    pub(super) fn wall_response(&self) -> WallResponse {
+        WallResponse {
+            support: self.smoothing_radius,
+            stiffness: self.boundary_stiffness,
+            damping: self.boundary_damping,
+            restitution: self.boundary_restitution,
+        }
+    }
+}
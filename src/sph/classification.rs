@@ -0,0 +1,200 @@
+use super::linalg::SymmetricMatrix2;
+use super::real::consts::FRAC_1_SQRT_2;
+use super::{
+    EPSILON, MIN_PAR_CHUNK, NeighborSearch, Real, SphConfig, SphSimulation, SurfaceTensionModel,
+    Vector,
+};
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParticleClass {
+    #[default]
+    Interior,
+    FreeSurface,
+    NearWall,
+    Isolated,
+}
+
+impl ParticleClass {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Interior => "interior",
+            Self::FreeSurface => "free_surface",
+            Self::NearWall => "near_wall",
+            Self::Isolated => "isolated",
+        }
+    }
+}
+
+impl SphSimulation {
+    pub fn particle_classes(&self) -> &[ParticleClass] {
+        &self.classes
+    }
+
+    pub fn surface_normals(&self) -> &[Vector] {
+        &self.surface_normals
+    }
+
+    pub fn refresh_classes(&mut self) {
+        if self.classes_stale {
+            self.refresh_neighbors();
+            self.classify_particles();
+        }
+    }
+
+    pub(super) fn classification_needed(&self) -> bool {
+        self.config.shifting_coefficient > 0.0
+            || self.config.surface_tension_model == SurfaceTensionModel::Akinci
+    }
+
+    pub(super) fn classify_particles(&mut self) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let inv_densities = &self.inv_densities;
+        let grid = &self.grid;
+
+        self.classes
+            .par_iter_mut()
+            .zip(self.surface_normals.par_iter_mut())
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, (class, normal))| {
+                let position_i = positions[index];
+                let mut color_gradient = Vector::ZERO;
+                let mut shape = SymmetricMatrix2::ZERO;
+                let mut neighbor_count = 0;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    if index == neighbor {
+                        return;
+                    }
+
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let volume = config.particle_mass * inv_densities[neighbor];
+                    let gradient = volume * kernels.spiky_gradient(delta, distance_sq.sqrt());
+
+                    color_gradient += gradient;
+                    shape = shape + SymmetricMatrix2::symmetric_outer(-delta, gradient);
+                    neighbor_count += 1;
+                });
+
+                *normal = color_gradient;
+                *class = Self::classify(
+                    config,
+                    position_i,
+                    color_gradient,
+                    shape.min_eigenvalue(),
+                    neighbor_count,
+                );
+            });
+        self.classes_stale = false;
+    }
+
+    fn classify(
+        config: SphConfig,
+        position: Vector,
+        color_gradient: Vector,
+        min_eigenvalue: Real,
+        neighbor_count: usize,
+    ) -> ParticleClass {
+        if neighbor_count < config.isolated_neighbor_count {
+            return ParticleClass::Isolated;
+        }
+
+        let wall_normal = Self::wall_normal(config, position);
+        let gradient_sq = color_gradient.length_squared();
+        let deficient = min_eigenvalue <= config.surface_eigenvalue_min
+            || (min_eigenvalue <= config.surface_eigenvalue_max
+                && gradient_sq > config.surface_threshold * config.surface_threshold);
+
+        if wall_normal == Vector::ZERO {
+            return if deficient {
+                ParticleClass::FreeSurface
+            } else {
+                ParticleClass::Interior
+            };
+        }
+
+        let wall_aligned = gradient_sq <= EPSILON
+            || color_gradient.dot(wall_normal)
+                >= FRAC_1_SQRT_2 * gradient_sq.sqrt() * wall_normal.length();
+
+        if deficient && !wall_aligned {
+            ParticleClass::FreeSurface
+        } else {
+            ParticleClass::NearWall
+        }
+    }
+
+    fn wall_normal(config: SphConfig, position: Vector) -> Vector {
+        let reach = config.smoothing_radius;
+        let mut normal = Vector::ZERO;
+
+        if position.x - config.bounds.left() < reach {
+            normal.x += 1.0;
+        }
+        if config.bounds.right() - position.x < reach {
+            normal.x -= 1.0;
+        }
+        if position.y - config.bounds.bottom() < reach {
+            normal.y += 1.0;
+        }
+        if config.bounds.top() - position.y < reach {
+            normal.y -= 1.0;
+        }
+
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn classifies_block_interior_surface_wall_and_splash() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        let origin = Vector::new(-1.0, config.bounds.bottom() + config.boundary_margin);
+        simulation.seed_block(20, 20, origin);
+        simulation.seed_block(1, 1, Vector::new(1.5, 1.5));
+
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+        simulation.classify_particles();
+
+        let classes = simulation.particle_classes();
+        assert_eq!(classes[10 * 20 + 10], ParticleClass::Interior);
+        assert_eq!(classes[19 * 20 + 10], ParticleClass::FreeSurface);
+        assert_eq!(classes[10], ParticleClass::NearWall);
+        assert_eq!(classes[400], ParticleClass::Isolated);
+    }
+
+    #[test]
+    fn lazy_classification_sees_particles_moved_after_the_step() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        simulation.step(1.0 / 240.0, None);
+        for position in &mut simulation.positions {
+            *position *= 0.6;
+        }
+
+        let mut eager = simulation.clone();
+        eager.rebuild_neighbors();
+        eager.classify_particles();
+        simulation.refresh_classes();
+
+        assert_eq!(simulation.classes, eager.classes);
+        assert_eq!(simulation.surface_normals, eager.surface_normals);
+    }
+}
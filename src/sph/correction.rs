@@ -0,0 +1,94 @@
+use super::linalg::Matrix2;
+use super::{EPSILON, MIN_PAR_CHUNK, NeighborSearch, Real, SphSimulation, Vector};
+use rayon::prelude::*;
+
+impl SphSimulation {
+    pub(super) fn compute_gradient_corrections(&mut self) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let inv_densities = &self.inv_densities;
+        let grid = &self.grid;
+
+        self.gradient_corrections
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, correction)| {
+                let position_i = positions[index];
+                let mut moments = Matrix2::ZERO;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let volume = config.particle_mass * inv_densities[neighbor];
+                    let gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+                    moments = moments + Matrix2::outer(volume * gradient, -delta);
+                });
+
+                *correction =
+                    correction_from_moments(moments, config.kernel_correction_min_determinant);
+            });
+    }
+}
+
+#[inline]
+pub(super) fn correction_from_moments(moments: Matrix2, min_determinant: Real) -> Matrix2 {
+    if moments.determinant() >= min_determinant {
+        moments.inverse().unwrap_or(Matrix2::IDENTITY)
+    } else {
+        Matrix2::IDENTITY
+    }
+}
+
+#[inline]
+pub(super) fn symmetric_correction(
+    corrections: &[Matrix2],
+    index: usize,
+    neighbor: usize,
+    gradient: Vector,
+) -> Vector {
+    0.5 * (corrections[index].transform(gradient) + corrections[neighbor].transform(gradient))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn corrected_gradient_is_exact_for_linear_fields_at_the_surface() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+        simulation.compute_gradient_corrections();
+
+        let slope = Vector::new(2.0, -3.0);
+        let surface = 19 * 20 + 10;
+        let position_i = simulation.positions[surface];
+        let correction = simulation.gradient_corrections[surface];
+        let mut raw = Vector::ZERO;
+        let mut corrected = Vector::ZERO;
+
+        for (neighbor, position_j) in simulation.positions.iter().enumerate() {
+            let delta = position_i - *position_j;
+            let gradient = simulation.kernels.spiky_gradient(delta, delta.length());
+            let difference =
+                slope.dot(-delta) * config.particle_mass * simulation.inv_densities[neighbor];
+            raw += difference * gradient;
+            corrected += difference * correction.transform(gradient);
+        }
+
+        assert!((raw - slope).length() > 0.2 * slope.length());
+        assert!((corrected - slope).length() < 1.0e-3 * slope.length());
+    }
+}
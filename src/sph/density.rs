@@ -0,0 +1,292 @@
+use super::correction::symmetric_correction;
+use super::linalg::solve3;
+use super::{EPSILON, MIN_PAR_CHUNK, NeighborSearch, Real, SphSimulation, Vector};
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DensityMethod {
+    Summation,
+    Continuity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DensityDiffusion {
+    None,
+    MolteniColagrossi,
+    Antuono,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DensityReinitialization {
+    None,
+    Shepard,
+    MovingLeastSquares,
+}
+
+impl SphSimulation {
+    pub(super) fn evolve_densities(&mut self, dt: Real) {
+        let interval = self.config.density_reinitialization_interval;
+        if interval > 0 && self.step_count.is_multiple_of(interval as u64) {
+            self.reinitialize_densities(self.config.density_reinitialization);
+        }
+
+        if self.config.density_diffusion == DensityDiffusion::Antuono {
+            self.compute_density_gradients();
+        }
+        self.compute_density_rates();
+
+        let minimum_density = self.config.rest_density * 0.5;
+        self.densities
+            .par_iter_mut()
+            .zip(self.density_rates.par_iter().copied())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(density, rate)| {
+                *density = (*density + rate * dt).max(minimum_density);
+            });
+    }
+
+    fn compute_density_gradients(&mut self) {
+        let kernels = self.kernels;
+        let particle_mass = self.config.particle_mass;
+        let correction_enabled = self.config.kernel_gradient_correction;
+        let gradient_corrections = &self.gradient_corrections;
+        let positions = &self.positions;
+        let densities = &self.densities;
+        let grid = &self.grid;
+
+        self.density_gradients
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, density_gradient)| {
+                let position_i = positions[index];
+                let density_i = densities[index];
+                let mut value = Vector::ZERO;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let mut gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+                    if correction_enabled {
+                        gradient =
+                            symmetric_correction(gradient_corrections, index, neighbor, gradient);
+                    }
+                    let density_j = densities[neighbor];
+                    value += particle_mass / density_j * (density_j - density_i) * gradient;
+                });
+
+                *density_gradient = value;
+            });
+    }
+
+    fn compute_density_rates(&mut self) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let velocities = &self.velocities;
+        let densities = &self.densities;
+        let density_gradients = &self.density_gradients;
+        let correction_enabled = config.kernel_gradient_correction;
+        let gradient_corrections = &self.gradient_corrections;
+        let grid = &self.grid;
+        let diffusion_scale =
+            config.density_diffusion_coefficient * config.smoothing_radius * config.sound_speed();
+
+        self.density_rates
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, rate)| {
+                let position_i = positions[index];
+                let velocity_i = velocities[index];
+                let density_i = densities[index];
+                let mut value = 0.0;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let mut gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+                    if correction_enabled {
+                        gradient =
+                            symmetric_correction(gradient_corrections, index, neighbor, gradient);
+                    }
+                    value +=
+                        config.particle_mass * (velocity_i - velocities[neighbor]).dot(gradient);
+
+                    let density_j = densities[neighbor];
+                    let mut psi = -2.0 * (density_j - density_i) * delta / distance_sq;
+                    match config.density_diffusion {
+                        DensityDiffusion::None => return,
+                        DensityDiffusion::MolteniColagrossi => {}
+                        DensityDiffusion::Antuono => {
+                            psi -= density_gradients[index] + density_gradients[neighbor];
+                        }
+                    }
+
+                    value += diffusion_scale * config.particle_mass / density_j * psi.dot(gradient);
+                });
+
+                *rate = value;
+            });
+    }
+
+    fn reinitialize_densities(&mut self, method: DensityReinitialization) {
+        if method == DensityReinitialization::None {
+            return;
+        }
+
+        let kernels = self.kernels;
+        let particle_mass = self.config.particle_mass;
+        let positions = &self.positions;
+        let densities = &self.densities;
+        let grid = &self.grid;
+        let inv_support = kernels.support_radius.recip();
+
+        let shepard = |index: usize| {
+            let position_i = positions[index];
+            let mut mass_sum = 0.0;
+            let mut volume_sum = 0.0;
+
+            grid.for_each_neighbor(index, |neighbor| {
+                let weight = kernels.poly6((position_i - positions[neighbor]).length_squared());
+                mass_sum += particle_mass * weight;
+                volume_sum += particle_mass / densities[neighbor] * weight;
+            });
+
+            mass_sum / volume_sum.max(EPSILON)
+        };
+
+        let moving_least_squares = |index: usize| {
+            let position_i = positions[index];
+            let mut moments = [[0.0; 3]; 3];
+
+            grid.for_each_neighbor(index, |neighbor| {
+                let delta = position_i - positions[neighbor];
+                let weight =
+                    kernels.poly6(delta.length_squared()) * particle_mass / densities[neighbor];
+                let basis = [1.0, delta.x * inv_support, delta.y * inv_support];
+
+                for (row, basis_row) in basis.iter().enumerate() {
+                    for (column, basis_column) in basis.iter().enumerate() {
+                        moments[row][column] += weight * basis_row * basis_column;
+                    }
+                }
+            });
+
+            let Some(beta) = solve3(moments, [1.0, 0.0, 0.0]) else {
+                return shepard(index);
+            };
+
+            let mut value = 0.0;
+            grid.for_each_neighbor(index, |neighbor| {
+                let delta = position_i - positions[neighbor];
+                let correction = beta[0] + (beta[1] * delta.x + beta[2] * delta.y) * inv_support;
+                value += particle_mass * correction * kernels.poly6(delta.length_squared());
+            });
+
+            value
+        };
+
+        let minimum_density = self.config.rest_density * 0.5;
+        let reinitialized = (0..positions.len())
+            .into_par_iter()
+            .with_min_len(MIN_PAR_CHUNK)
+            .map(|index| {
+                let value = match method {
+                    DensityReinitialization::Shepard => shepard(index),
+                    _ => moving_least_squares(index),
+                };
+                value.max(minimum_density)
+            })
+            .collect::<Vec<_>>();
+
+        self.densities = reinitialized;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+    use nannou::prelude::*;
+
+    fn resting_block(config: SphConfig) -> SphSimulation {
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        simulation.grid.rebuild(&simulation.positions);
+        simulation
+    }
+
+    #[test]
+    fn continuity_density_grows_under_compression() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.density_diffusion = DensityDiffusion::MolteniColagrossi;
+        let mut simulation = resting_block(config);
+        let center = simulation.positions[10 * 20 + 10];
+        for (velocity, position) in simulation
+            .velocities
+            .iter_mut()
+            .zip(simulation.positions.iter())
+        {
+            *velocity = -(*position - center);
+        }
+
+        simulation.compute_density_rates();
+
+        let rate = simulation.density_rates[10 * 20 + 10];
+        assert!((rate - 2.0 * config.rest_density).abs() < 0.2 * 2.0 * config.rest_density);
+    }
+
+    #[test]
+    fn corrected_continuity_rate_is_consistent_at_the_free_surface() {
+        let expected = 2.0 * SphConfig::new(Rect::from_w_h(4.0, 4.0)).rest_density;
+        let surface = 19 * 20 + 10;
+        let errors = [false, true].map(|corrected| {
+            let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+            config.density_diffusion = DensityDiffusion::None;
+            config.kernel_gradient_correction = corrected;
+            let mut simulation = resting_block(config);
+            let center = simulation.positions[10 * 20 + 10];
+            for (velocity, position) in simulation
+                .velocities
+                .iter_mut()
+                .zip(simulation.positions.iter())
+            {
+                *velocity = -(*position - center);
+            }
+
+            simulation.compute_gradient_corrections();
+            simulation.compute_density_rates();
+            (simulation.density_rates[surface] - expected).abs() / expected
+        });
+
+        assert!(errors[0] > 0.3);
+        assert!(errors[1] < 0.5 * errors[0]);
+    }
+
+    #[test]
+    fn reinitialization_keeps_rest_density_at_the_free_surface() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let surface = 19 * 20 + 10;
+
+        for method in [
+            DensityReinitialization::Shepard,
+            DensityReinitialization::MovingLeastSquares,
+        ] {
+            let mut simulation = resting_block(config);
+            simulation.reinitialize_densities(method);
+
+            assert!((simulation.densities[surface] - config.rest_density).abs() < 1.0);
+        }
+    }
+}
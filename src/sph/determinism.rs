@@ -0,0 +1,143 @@
+use super::{Real, SphSimulation};
+use rayon::prelude::*;
+
+const REDUCTION_CHUNK: usize = 1024;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+pub(super) const SEED_JITTER_STREAM: u64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    pub fn next_real(&mut self) -> Real {
+        let bits = Real::MANTISSA_DIGITS;
+        (self.next_u64() >> (64 - bits)) as Real / (1u64 << bits) as Real
+    }
+}
+
+impl SphSimulation {
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    pub fn particle_rng(&self, id: u32, stream: u64) -> SplitMix64 {
+        let seed = [self.step_count, id as u64, stream]
+            .into_iter()
+            .fold(self.config.random_seed, |seed, value| {
+                SplitMix64::new(seed ^ value).next_u64()
+            });
+        SplitMix64::new(seed)
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        write(&self.step_count.to_le_bytes());
+        write(&(self.id_slots.len() as u64).to_le_bytes());
+        for slot in &self.id_slots {
+            let slot = *slot as usize;
+            let position = self.positions[slot];
+            let velocity = self.velocities[slot];
+            for value in [
+                position.x,
+                position.y,
+                velocity.x,
+                velocity.y,
+                self.densities[slot],
+            ] {
+                write(&value.to_le_bytes());
+            }
+        }
+
+        hash
+    }
+}
+
+pub(super) fn reduce<I, T, ID, OP>(iter: I, deterministic: bool, identity: ID, combine: OP) -> T
+where
+    I: IndexedParallelIterator<Item = T>,
+    T: Send,
+    ID: Fn() -> T + Send + Sync,
+    OP: Fn(T, T) -> T + Send + Sync,
+{
+    if !deterministic {
+        return iter.reduce(identity, combine);
+    }
+
+    iter.fold_chunks(REDUCTION_CHUNK, &identity, &combine)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .fold(identity(), &combine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{SphConfig, Vector};
+    use super::*;
+    use nannou::prelude::*;
+
+    fn run(threads: usize, deterministic: bool, seed: u64) -> (u64, Real, Real) {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| {
+                let mut config = SphConfig::new(Rect::from_w_h(3.0, 3.0));
+                config.deterministic = deterministic;
+                config.random_seed = seed;
+                config.seed_jitter = 0.1;
+                let mut simulation = SphSimulation::new(config);
+                simulation.seed_block(40, 30, Vector::new(-1.2, -1.2));
+                for _ in 0..20 {
+                    simulation.step(1.0 / 240.0, None);
+                }
+                simulation.refresh_stats();
+                let energy = simulation.stats().energy;
+                (
+                    simulation.state_hash(),
+                    energy.kinetic,
+                    energy.total_dissipation.viscosity,
+                )
+            })
+    }
+
+    #[test]
+    fn deterministic_runs_match_across_thread_counts() {
+        let serial = run(1, true, 7);
+        let parallel = run(3, true, 7);
+
+        assert_eq!(serial.0, parallel.0);
+        assert_eq!(serial.1.to_bits(), parallel.1.to_bits());
+        assert_eq!(serial.2.to_bits(), parallel.2.to_bits());
+        assert_ne!(serial.0, run(1, true, 8).0);
+        assert_ne!(run(3, false, 7).0, run(3, false, 8).0);
+
+        let mut first = SplitMix64::new(7);
+        let mut second = SplitMix64::new(7);
+        for _ in 0..16 {
+            let value = first.next_real();
+            assert_eq!(value.to_bits(), second.next_real().to_bits());
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+}
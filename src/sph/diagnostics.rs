@@ -0,0 +1,305 @@
+use super::determinism;
+use super::{
+    MIN_PAR_CHUNK, ParticleClass, Real, Sph3dConfig, SphConfig, SphSimulation, Vector, Wall,
+};
+use rayon::prelude::*;
+use std::ops::Add;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DissipationBreakdown {
+    pub viscosity: Real,
+    pub artificial_viscosity: Real,
+    pub porous: Real,
+    pub xsph: Real,
+    pub velocity_damping: Real,
+    pub velocity_clamp: Real,
+    pub boundary: Real,
+}
+
+impl DissipationBreakdown {
+    pub fn total(&self) -> Real {
+        self.viscosity
+            + self.artificial_viscosity
+            + self.porous
+            + self.xsph
+            + self.velocity_damping
+            + self.velocity_clamp
+            + self.boundary
+    }
+}
+
+impl Add for DissipationBreakdown {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            viscosity: self.viscosity + other.viscosity,
+            artificial_viscosity: self.artificial_viscosity + other.artificial_viscosity,
+            porous: self.porous + other.porous,
+            xsph: self.xsph + other.xsph,
+            velocity_damping: self.velocity_damping + other.velocity_damping,
+            velocity_clamp: self.velocity_clamp + other.velocity_clamp,
+            boundary: self.boundary + other.boundary,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnergyReport {
+    pub kinetic: Real,
+    pub potential: Real,
+    pub elastic: Real,
+    pub momentum: Vector,
+    pub angular_momentum: Real,
+    pub mass: Real,
+    pub volume: Real,
+    pub volume_drift: Real,
+    pub step_dissipation: DissipationBreakdown,
+    pub total_dissipation: DissipationBreakdown,
+}
+
+impl EnergyReport {
+    pub fn mechanical(&self) -> Real {
+        self.kinetic + self.potential + self.elastic
+    }
+
+    fn combine(self, other: Self) -> Self {
+        Self {
+            kinetic: self.kinetic + other.kinetic,
+            potential: self.potential + other.potential,
+            elastic: self.elastic + other.elastic,
+            momentum: self.momentum + other.momentum,
+            angular_momentum: self.angular_momentum + other.angular_momentum,
+            mass: self.mass + other.mass,
+            volume: self.volume + other.volume,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct DissipativeTerms {
+    pub(super) viscosity: Vector,
+    pub(super) artificial_viscosity: Vector,
+    pub(super) porous_drag: Vector,
+    pub(super) boundary_damping: Vector,
+}
+
+#[inline]
+pub(super) fn kinetic_energy(mass: Real, velocity: Vector) -> Real {
+    0.5 * mass * velocity.length_squared()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct EquationOfState {
+    rest_density: Real,
+    stiffness: Real,
+    gamma: Real,
+    max_density_ratio: Real,
+}
+
+impl EquationOfState {
+    // Integral of p / rho^2 from the rest density, using the same clamped ratio as the equation of state.
+    #[inline]
+    pub(super) fn elastic_energy_per_mass(&self, density: Real) -> Real {
+        let ratio = (density / self.rest_density).clamp(1.0, self.max_density_ratio);
+        let exponent = self.gamma - 1.0;
+
+        self.stiffness / self.rest_density
+            * ((ratio.powf(exponent) - 1.0) / exponent + ratio.recip() - 1.0)
+    }
+}
+
+impl SphConfig {
+    pub(super) fn equation_of_state(&self) -> EquationOfState {
+        EquationOfState {
+            rest_density: self.rest_density,
+            stiffness: self.pressure_stiffness,
+            gamma: self.gamma,
+            max_density_ratio: self.max_density_ratio,
+        }
+    }
+}
+
+impl Sph3dConfig {
+    pub(super) fn equation_of_state(&self) -> EquationOfState {
+        EquationOfState {
+            rest_density: self.rest_density,
+            stiffness: self.pressure_stiffness,
+            gamma: self.gamma,
+            max_density_ratio: self.max_density_ratio,
+        }
+    }
+}
+
+impl SphSimulation {
+    pub(super) fn energy_report(&self) -> EnergyReport {
+        let config = &self.config;
+        let particle_mass = config.particle_mass;
+        let datum = Vector::new(config.bounds.left(), config.bounds.bottom());
+        let equation_of_state = config.equation_of_state();
+
+        let report = determinism::reduce(
+            self.positions
+                .par_iter()
+                .zip(self.velocities.par_iter())
+                .zip(self.densities.par_iter())
+                .with_min_len(MIN_PAR_CHUNK)
+                .map(|((position, velocity), density)| {
+                    let momentum = particle_mass * *velocity;
+
+                    EnergyReport {
+                        kinetic: kinetic_energy(particle_mass, *velocity),
+                        potential: -particle_mass * config.gravity.dot(*position - datum)
+                            + particle_mass * Self::centrifugal_potential(*config, *position),
+                        elastic: particle_mass
+                            * equation_of_state.elastic_energy_per_mass(*density),
+                        momentum,
+                        angular_momentum: position.perp_dot(momentum),
+                        mass: particle_mass,
+                        volume: particle_mass / density.max(Real::EPSILON),
+                        ..EnergyReport::default()
+                    }
+                }),
+            config.deterministic,
+            EnergyReport::default,
+            EnergyReport::combine,
+        );
+
+        EnergyReport {
+            // Particle masses are constant, so conservation shows up as drift of sum(m / rho_i).
+            volume_drift: relative_drift(report.volume, self.reference_volume),
+            step_dissipation: self.step_dissipation,
+            total_dissipation: self.total_dissipation,
+            ..report
+        }
+    }
+
+    pub(super) fn measured_contact_angles(&self) -> [Option<Real>; 4] {
+        let config = &self.config;
+
+        Wall::ALL.map(|wall| {
+            let (sum, count) = self
+                .positions
+                .iter()
+                .zip(&self.surface_normals)
+                .zip(&self.classes)
+                .filter(|((position, _), class)| {
+                    **class == ParticleClass::FreeSurface
+                        && wall.distance(config.bounds, **position) < config.smoothing_radius
+                })
+                .filter_map(|((_, normal), _)| Self::contact_angle(wall, *normal))
+                .fold((0.0, 0), |(sum, count), angle| (sum + angle, count + 1));
+
+            (count > 0).then(|| sum / count as Real)
+        })
+    }
+}
+
+#[inline]
+pub(super) fn relative_drift(value: Real, reference: Real) -> Real {
+    if reference > 0.0 {
+        (value - reference) / reference
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DensityMethod, SurfaceTensionModel};
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn free_fall_energy_is_accounted_for() {
+        let mut config = SphConfig::new(Rect::from_w_h(8.0, 8.0));
+        config.max_velocity = 100.0;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(1, 1, Vector::new(0.0, 2.0));
+        let initial = simulation.energy_report().mechanical();
+
+        for _ in 0..100 {
+            simulation.step(1.0 / 240.0, None);
+        }
+
+        let report = simulation.energy_report();
+        let balance = report.mechanical() + report.total_dissipation.total();
+
+        assert!(report.total_dissipation.velocity_damping > 0.0);
+        assert!((balance - initial).abs() / initial < 0.05);
+    }
+
+    #[test]
+    fn volume_drift_stays_at_rest_and_grows_under_compression() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.density_method = DensityMethod::Continuity;
+        config.gravity = Vector::ZERO;
+        config.surface_tension = 0.0;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(16, 16, Vector::new(-0.4, -0.4));
+
+        for _ in 0..20 {
+            simulation.step(1.0 / 240.0, None);
+        }
+        let resting = simulation.energy_report().volume_drift;
+        assert!(resting.abs() < 1.0e-3);
+
+        let center = Vector::new(0.0, 0.0);
+        for (velocity, position) in simulation
+            .velocities
+            .iter_mut()
+            .zip(simulation.positions.iter())
+        {
+            *velocity = -2.0 * (*position - center);
+        }
+        for _ in 0..5 {
+            simulation.step(1.0 / 240.0, None);
+        }
+        let compressed = simulation.energy_report().volume_drift;
+        assert!(compressed < -0.02);
+    }
+
+    #[test]
+    fn contact_angles_are_measured_only_where_the_fluid_meets_a_wall() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        let origin = Vector::new(-0.5, config.bounds.bottom() + config.boundary_margin);
+        simulation.seed_block(20, 10, origin);
+        simulation.step(1.0 / 240.0, None);
+        simulation.refresh_classes();
+
+        let angles = simulation.measured_contact_angles();
+        assert!(angles[Wall::Bottom as usize].is_some());
+        assert_eq!(angles[Wall::Top as usize], None);
+    }
+
+    #[test]
+    fn hydrophobic_floor_beads_the_droplet_that_a_hydrophilic_floor_spreads() {
+        let footprint = |degrees: Real| {
+            let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+            config.surface_tension_model = SurfaceTensionModel::Akinci;
+            config.gravity = Vector::new(0.0, -1.0);
+            config.wall_materials[Wall::Bottom as usize].contact_angle =
+                Some(Real::to_radians(degrees));
+            let mut simulation = SphSimulation::new(config);
+            let origin = Vector::new(-0.3, config.bounds.bottom() + config.boundary_margin);
+            simulation.seed_block(12, 12, origin);
+            for _ in 0..720 {
+                simulation.step(1.0 / 240.0, None);
+            }
+
+            let floor = config.bounds.bottom() + config.smoothing_radius;
+            let (left, right) = simulation
+                .positions
+                .iter()
+                .filter(|position| position.y < floor)
+                .fold((Real::MAX, Real::MIN), |(left, right), position| {
+                    (left.min(position.x), right.max(position.x))
+                });
+            right - left
+        };
+
+        assert!(footprint(30.0) > 1.1 * footprint(150.0));
+    }
+}
@@ -0,0 +1,311 @@
+use super::SphSimulation;
+use super::real::{NPY_DESCR, Real, VTK_FLOAT};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+const NPY_ALIGNMENT: usize = 64;
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+impl SphSimulation {
+    pub fn write_particles_csv(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.refresh_classes();
+        self.refresh_vorticities();
+        let mut writer = create(path.as_ref())?;
+
+        write_particle_rows(
+            &mut writer,
+            &["vorticity", "normal_x", "normal_y", "class", "phase"],
+            self.id_slots.iter().map(|slot| {
+                let index = *slot as usize;
+                ParticleRow {
+                    index,
+                    position: self.positions[index].to_array(),
+                    velocity: self.reported_velocity(index).to_array(),
+                    density: self.densities[index],
+                    pressure: self.pressures[index],
+                }
+            }),
+            |writer, index| {
+                let normal = self.surface_normals[index];
+                write!(
+                    writer,
+                    ",{},{},{},{},{}",
+                    self.vorticities[index],
+                    normal.x,
+                    normal.y,
+                    self.classes[index].label(),
+                    self.phases[index].label(),
+                )
+            },
+        )?;
+
+        writer.flush()
+    }
+
+    pub fn write_particles_vtp(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.refresh_vorticities();
+        let mut writer = create(path.as_ref())?;
+
+        write_particle_points(
+            &mut writer,
+            &self
+                .id_slots
+                .iter()
+                .map(|slot| *slot as usize)
+                .collect::<Vec<_>>(),
+            |index| self.positions[index].to_array(),
+            |index| self.reported_velocity(index).to_array(),
+            &[
+                ("density", &self.densities),
+                ("pressure", &self.pressures),
+                ("vorticity", &self.vorticities),
+            ],
+        )?;
+
+        writer.flush()
+    }
+}
+
+pub(super) struct ParticleRow<const N: usize> {
+    pub(super) index: usize,
+    pub(super) position: [Real; N],
+    pub(super) velocity: [Real; N],
+    pub(super) density: Real,
+    pub(super) pressure: Real,
+}
+
+pub(super) fn write_particle_rows<W: Write, const N: usize>(
+    writer: &mut W,
+    extra_columns: &[&str],
+    rows: impl Iterator<Item = ParticleRow<N>>,
+    mut write_extra: impl FnMut(&mut W, usize) -> io::Result<()>,
+) -> io::Result<()> {
+    let axes = &AXES[..N];
+
+    write!(writer, "id")?;
+    for axis in axes {
+        write!(writer, ",{axis}")?;
+    }
+    for axis in axes {
+        write!(writer, ",velocity_{axis}")?;
+    }
+    write!(writer, ",density,pressure")?;
+    for column in extra_columns {
+        write!(writer, ",{column}")?;
+    }
+    writeln!(writer)?;
+
+    for (id, row) in rows.enumerate() {
+        write!(writer, "{id}")?;
+        for value in row.position.iter().chain(&row.velocity) {
+            write!(writer, ",{value}")?;
+        }
+        write!(writer, ",{},{}", row.density, row.pressure)?;
+        write_extra(writer, row.index)?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn write_particle_points<W: Write, const N: usize>(
+    writer: &mut W,
+    slots: &[usize],
+    position: impl Fn(usize) -> [Real; N],
+    velocity: impl Fn(usize) -> [Real; N],
+    scalars: &[(&str, &[Real])],
+) -> io::Result<()> {
+    let count = slots.len();
+    let ids = (0..count).collect::<Vec<_>>();
+
+    writeln!(writer, "<?xml version=\"1.0\"?>")?;
+    writeln!(
+        writer,
+        "<VTKFile type=\"PolyData\" version=\"0.1\" byte_order=\"LittleEndian\">"
+    )?;
+    writeln!(
+        writer,
+        "<PolyData>\n<Piece NumberOfPoints=\"{count}\" NumberOfVerts=\"{count}\">"
+    )?;
+    writeln!(writer, "<Points>")?;
+    write_vtk_vectors(writer, "points", slots.iter().map(|slot| position(*slot)))?;
+    writeln!(
+        writer,
+        "</Points>\n<PointData Scalars=\"{}\" Vectors=\"velocity\">",
+        scalars.first().map_or("id", |(name, _)| *name)
+    )?;
+    write_vtk_indices(writer, "id", &ids)?;
+    write_vtk_vectors(writer, "velocity", slots.iter().map(|slot| velocity(*slot)))?;
+    for (name, values) in scalars {
+        write_vtk_scalars(writer, name, slots.iter().map(|slot| &values[*slot]))?;
+    }
+    writeln!(writer, "</PointData>\n<Verts>")?;
+    write_vtk_indices(writer, "connectivity", &ids)?;
+    write_vtk_indices(writer, "offsets", &(1..=count).collect::<Vec<_>>())?;
+    writeln!(writer, "</Verts>\n</Piece>\n</PolyData>\n</VTKFile>")
+}
+
+pub(super) fn create(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+pub(super) fn write_npy<W: Write>(
+    writer: &mut W,
+    shape: &[usize],
+    data: &[Real],
+) -> io::Result<()> {
+    debug_assert_eq!(shape.iter().product::<usize>(), data.len());
+
+    let dims = shape
+        .iter()
+        .map(|dim| dim.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let shape = if shape.len() == 1 {
+        format!("({dims},)")
+    } else {
+        format!("({dims})")
+    };
+    let mut header =
+        format!("{{'descr': '{NPY_DESCR}', 'fortran_order': False, 'shape': {shape}, }}");
+    let unpadded = NPY_MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT));
+    header.push('\n');
+
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn write_vtk_scalars<'a, W: Write>(
+    writer: &mut W,
+    name: &str,
+    values: impl IntoIterator<Item = &'a Real>,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "<DataArray type=\"{VTK_FLOAT}\" Name=\"{name}\" format=\"ascii\">"
+    )?;
+    for value in values {
+        write!(writer, "{value} ")?;
+    }
+    writeln!(writer, "\n</DataArray>")
+}
+
+pub(super) fn write_vtk_vectors<W: Write, const N: usize>(
+    writer: &mut W,
+    name: &str,
+    values: impl Iterator<Item = [Real; N]>,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "<DataArray type=\"{VTK_FLOAT}\" Name=\"{name}\" NumberOfComponents=\"3\" format=\"ascii\">"
+    )?;
+    for value in values {
+        for component in value {
+            write!(writer, "{component} ")?;
+        }
+        for _ in N..3 {
+            write!(writer, "0 ")?;
+        }
+    }
+    writeln!(writer, "\n</DataArray>")
+}
+
+pub(super) fn write_vtk_indices<W: Write>(
+    writer: &mut W,
+    name: &str,
+    values: &[usize],
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "<DataArray type=\"Int64\" Name=\"{name}\" format=\"ascii\">"
+    )?;
+    for value in values {
+        write!(writer, "{value} ")?;
+    }
+    writeln!(writer, "\n</DataArray>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &[2, 3], &[0.0; 6]).unwrap();
+
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+
+        assert_eq!((10 + header_len) % NPY_ALIGNMENT, 0);
+        assert_eq!(bytes.len(), 10 + header_len + 6 * size_of::<Real>());
+        assert_eq!(bytes[10 + header_len - 1], b'\n');
+    }
+
+    #[test]
+    fn particle_rows_name_every_axis() {
+        let mut bytes = Vec::new();
+        let row = ParticleRow {
+            index: 4,
+            position: [1.0, 2.0, 3.0],
+            velocity: [0.5, 0.0, -0.5],
+            density: 1000.0,
+            pressure: 0.0,
+        };
+        write_particle_rows(
+            &mut bytes,
+            &["slot"],
+            std::iter::once(row),
+            |writer, index| write!(writer, ",{index}"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "id,x,y,z,velocity_x,velocity_y,velocity_z,density,pressure,slot\n\
+             0,1,2,3,0.5,0,-0.5,1000,0,4\n"
+        );
+    }
+
+    #[test]
+    fn particle_points_follow_slot_order_with_vertex_cells() {
+        let mut bytes = Vec::new();
+        let densities = [10.0, 20.0, 30.0];
+        write_particle_points(
+            &mut bytes,
+            &[2, 0, 1],
+            |slot| [slot as Real, 0.0],
+            |_| [0.0, 0.0],
+            &[("density", &densities)],
+        )
+        .unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let array = |name: &str| {
+            let start = text.find(&format!("Name=\"{name}\"")).unwrap();
+            let body = &text[start..];
+            let body = &body[body.find('\n').unwrap() + 1..];
+            body[..body.find('\n').unwrap()].trim().to_string()
+        };
+
+        assert!(text.contains("NumberOfPoints=\"3\" NumberOfVerts=\"3\""));
+        assert_eq!(array("points"), "2 0 0 0 0 0 1 0 0");
+        assert_eq!(array("density"), "30 10 20");
+        assert_eq!(array("id"), "0 1 2");
+        assert_eq!(array("connectivity"), "0 1 2");
+        assert_eq!(array("offsets"), "1 2 3");
+    }
+}
@@ -0,0 +1,261 @@
+use super::export;
+use super::{EPSILON, MIN_PAR_CHUNK, NeighborGrid, NeighborSearch, Real, SphSimulation, Vector};
+use nannou::prelude::*;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::path::Path;
+
+const MIN_COVERAGE: Real = 1.0e-3;
+
+pub const FIELD_CHANNELS: [&str; 7] = [
+    "coverage",
+    "density",
+    "pressure",
+    "velocity_x",
+    "velocity_y",
+    "vorticity",
+    "divergence",
+];
+
+#[derive(Clone, Debug)]
+pub struct EulerianField {
+    pub bounds: Rect<Real>,
+    pub cols: usize,
+    pub rows: usize,
+    pub coverage: Vec<Real>,
+    pub density: Vec<Real>,
+    pub pressure: Vec<Real>,
+    pub velocity: Vec<Vector>,
+    pub vorticity: Vec<Real>,
+    pub divergence: Vec<Real>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FieldSample {
+    coverage: Real,
+    density: Real,
+    pressure: Real,
+    velocity: Vector,
+    vorticity: Real,
+    divergence: Real,
+}
+
+impl EulerianField {
+    pub fn spacing(&self) -> Vector {
+        Vector::new(
+            self.bounds.w() / (self.cols - 1) as Real,
+            self.bounds.h() / (self.rows - 1) as Real,
+        )
+    }
+
+    pub fn node_position(&self, col: usize, row: usize) -> Vector {
+        Vector::new(self.bounds.left(), self.bounds.bottom())
+            + Vector::new(col as Real, row as Real) * self.spacing()
+    }
+
+    pub fn channel(&self, node: usize) -> [Real; FIELD_CHANNELS.len()] {
+        [
+            self.coverage[node],
+            self.density[node],
+            self.pressure[node],
+            self.velocity[node].x,
+            self.velocity[node].y,
+            self.vorticity[node],
+            self.divergence[node],
+        ]
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+        writeln!(writer, "x,y,{}", FIELD_CHANNELS.join(","))?;
+
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let position = self.node_position(col, row);
+                write!(writer, "{},{}", position.x, position.y)?;
+                for value in self.channel(row * self.cols + col) {
+                    write!(writer, ",{value}")?;
+                }
+                writeln!(writer)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    pub fn write_npy(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+        let data = (0..self.cols * self.rows)
+            .flat_map(|node| self.channel(node))
+            .collect::<Vec<_>>();
+
+        export::write_npy(
+            &mut writer,
+            &[self.rows, self.cols, FIELD_CHANNELS.len()],
+            &data,
+        )?;
+        writer.flush()
+    }
+
+    pub fn write_vti(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+        let spacing = self.spacing();
+        let extent = format!("0 {} 0 {} 0 0", self.cols - 1, self.rows - 1);
+
+        writeln!(writer, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            writer,
+            "<VTKFile type=\"ImageData\" version=\"0.1\" byte_order=\"LittleEndian\">"
+        )?;
+        writeln!(
+            writer,
+            "<ImageData WholeExtent=\"{extent}\" Origin=\"{} {} 0\" Spacing=\"{} {} 1\">",
+            self.bounds.left(),
+            self.bounds.bottom(),
+            spacing.x,
+            spacing.y,
+        )?;
+        writeln!(writer, "<Piece Extent=\"{extent}\">")?;
+        writeln!(
+            writer,
+            "<PointData Scalars=\"density\" Vectors=\"velocity\">"
+        )?;
+        export::write_vtk_scalars(&mut writer, "coverage", &self.coverage)?;
+        export::write_vtk_scalars(&mut writer, "density", &self.density)?;
+        export::write_vtk_scalars(&mut writer, "pressure", &self.pressure)?;
+        export::write_vtk_vectors(
+            &mut writer,
+            "velocity",
+            self.velocity.iter().map(|velocity| velocity.to_array()),
+        )?;
+        export::write_vtk_scalars(&mut writer, "vorticity", &self.vorticity)?;
+        export::write_vtk_scalars(&mut writer, "divergence", &self.divergence)?;
+        writeln!(writer, "</PointData>\n</Piece>\n</ImageData>\n</VTKFile>")?;
+
+        writer.flush()
+    }
+}
+
+impl SphSimulation {
+    pub fn rasterize(&self, cols: usize, rows: usize) -> EulerianField {
+        let cols = cols.max(2);
+        let rows = rows.max(2);
+        let samples = self.sample_nodes(cols, rows, |grid, position| {
+            self.sample_field(grid, position)
+        });
+
+        EulerianField {
+            bounds: self.config.bounds,
+            cols,
+            rows,
+            coverage: samples.iter().map(|sample| sample.coverage).collect(),
+            density: samples.iter().map(|sample| sample.density).collect(),
+            pressure: samples.iter().map(|sample| sample.pressure).collect(),
+            velocity: samples.iter().map(|sample| sample.velocity).collect(),
+            vorticity: samples.iter().map(|sample| sample.vorticity).collect(),
+            divergence: samples.iter().map(|sample| sample.divergence).collect(),
+        }
+    }
+
+    pub(super) fn sample_nodes<T, F>(&self, cols: usize, rows: usize, sample: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&NeighborGrid, Vector) -> T + Sync,
+    {
+        let bounds = self.config.bounds;
+        let spacing = Vector::new(
+            bounds.w() / (cols - 1) as Real,
+            bounds.h() / (rows - 1) as Real,
+        );
+        let origin = Vector::new(bounds.left(), bounds.bottom());
+
+        let mut grid = NeighborGrid::new(&self.config);
+        grid.rebuild(&self.positions);
+
+        (0..cols * rows)
+            .into_par_iter()
+            .with_min_len(MIN_PAR_CHUNK)
+            .map(|node| {
+                let position =
+                    origin + Vector::new((node % cols) as Real, (node / cols) as Real) * spacing;
+                sample(&grid, position)
+            })
+            .collect()
+    }
+
+    fn sample_field(&self, grid: &NeighborGrid, position: Vector) -> FieldSample {
+        let kernels = self.kernels;
+        let particle_mass = self.config.particle_mass;
+        let mut sample = FieldSample::default();
+        let mut gradient_sum = Vector::ZERO;
+        let mut velocity_divergence = 0.0;
+        let mut velocity_curl = 0.0;
+
+        grid.for_each_near(position, |neighbor| {
+            let delta = position - self.positions[neighbor];
+            let distance_sq = delta.length_squared();
+
+            if distance_sq >= kernels.support_sq {
+                return;
+            }
+
+            let volume = particle_mass * self.inv_densities[neighbor];
+            let weight = volume * kernels.poly6(distance_sq);
+            let gradient = volume * kernels.spiky_gradient(delta, distance_sq.sqrt());
+            let velocity = self.velocities[neighbor];
+
+            sample.coverage += weight;
+            sample.density += weight * self.densities[neighbor];
+            sample.pressure += weight * self.pressures[neighbor];
+            sample.velocity += weight * velocity;
+            gradient_sum += gradient;
+            velocity_divergence += velocity.dot(gradient);
+            velocity_curl += gradient.perp_dot(velocity);
+        });
+
+        if sample.coverage < MIN_COVERAGE {
+            return FieldSample {
+                coverage: sample.coverage,
+                ..FieldSample::default()
+            };
+        }
+
+        let inv_coverage = sample.coverage.max(EPSILON).recip();
+        let velocity = sample.velocity * inv_coverage;
+
+        FieldSample {
+            coverage: sample.coverage,
+            density: sample.density * inv_coverage,
+            pressure: sample.pressure * inv_coverage,
+            velocity,
+            vorticity: velocity_curl - gradient_sum.perp_dot(velocity),
+            divergence: velocity_divergence - velocity.dot(gradient_sum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+
+    #[test]
+    fn rasterized_rest_block_matches_particle_state() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(30, 30, Vector::new(-1.6, -1.6));
+        for velocity in &mut simulation.velocities {
+            *velocity = Vector::new(0.5, 0.0);
+        }
+
+        let field = simulation.rasterize(41, 41);
+        let inside = 12 * field.cols + 12;
+        let outside = 38 * field.cols + 38;
+
+        assert!((field.density[inside] - config.rest_density).abs() < 1.0e-2);
+        assert!((field.velocity[inside] - Vector::new(0.5, 0.0)).length() < 1.0e-4);
+        assert!(field.vorticity[inside].abs() < 1.0e-3);
+        assert!(field.divergence[inside].abs() < 1.0e-3);
+        assert_eq!(field.coverage[outside], 0.0);
+    }
+}
@@ -0,0 +1,121 @@
+use super::linalg::SymmetricMatrix2;
+use super::real::consts::FRAC_1_SQRT_2;
+use super::{EPSILON, MIN_PAR_CHUNK, NeighborSearch, Phase, Real, SphSimulation};
+use rayon::prelude::*;
+
+impl SphSimulation {
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    pub(super) fn update_granular_stresses(&mut self, dt: Real) {
+        let config = self.config;
+        let material = config.granular;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let velocities = &self.velocities;
+        let inv_densities = &self.inv_densities;
+        let pressures = &self.pressures;
+        let phases = &self.phases;
+        let grid = &self.grid;
+
+        self.deviatoric_stresses
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, stress)| {
+                if phases[index] != Phase::Granular {
+                    *stress = SymmetricMatrix2::ZERO;
+                    return;
+                }
+
+                let position_i = positions[index];
+                let velocity_i = velocities[index];
+                let mut velocity_gradient = [[0.0; 2]; 2];
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    if phases[neighbor] != Phase::Granular {
+                        return;
+                    }
+
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let volume = config.particle_mass * inv_densities[neighbor];
+                    let gradient = volume * kernels.spiky_gradient(delta, distance_sq.sqrt());
+                    let relative = velocities[neighbor] - velocity_i;
+
+                    velocity_gradient[0][0] += relative.x * gradient.x;
+                    velocity_gradient[0][1] += relative.x * gradient.y;
+                    velocity_gradient[1][0] += relative.y * gradient.x;
+                    velocity_gradient[1][1] += relative.y * gradient.y;
+                });
+
+                let shear = 0.5 * (velocity_gradient[0][1] + velocity_gradient[1][0]);
+                let spin = 0.5 * (velocity_gradient[0][1] - velocity_gradient[1][0]);
+                let mean = 0.5 * (velocity_gradient[0][0] + velocity_gradient[1][1]);
+                let current = *stress;
+
+                let rate = SymmetricMatrix2 {
+                    xx: 2.0 * material.shear_modulus * (velocity_gradient[0][0] - mean)
+                        + 2.0 * spin * current.xy,
+                    xy: 2.0 * material.shear_modulus * shear + spin * (current.yy - current.xx),
+                    yy: 2.0 * material.shear_modulus * (velocity_gradient[1][1] - mean)
+                        - 2.0 * spin * current.xy,
+                };
+                let trial = current + rate.scaled(dt);
+
+                let invariant = trial.norm() * FRAC_1_SQRT_2;
+                let yield_stress = material.yield_stress(pressures[index]);
+                *stress = if invariant > yield_stress {
+                    trial.scaled(yield_stress / invariant)
+                } else {
+                    trial
+                };
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{GranularMaterial, SphConfig, Vector};
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn drucker_prager_caps_the_shear_stress() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block_with_phase(20, 20, Vector::new(-0.5, -0.5), Phase::Granular);
+        for (velocity, position) in simulation
+            .velocities
+            .iter_mut()
+            .zip(simulation.positions.iter())
+        {
+            *velocity = Vector::new(position.y, 0.0);
+        }
+
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+        let center = 10 * 20 + 10;
+        simulation.pressures[center] = 100.0;
+
+        simulation.update_granular_stresses(1.0e-4);
+        let elastic = simulation.deviatoric_stresses[center];
+        assert!(elastic.xy > 0.0);
+        assert!(elastic.xy < config.granular.yield_stress(100.0));
+
+        for _ in 0..100 {
+            simulation.update_granular_stresses(1.0e-2);
+        }
+        let yielded = simulation.deviatoric_stresses[center].norm() * FRAC_1_SQRT_2;
+        let cap = 100.0 * (3.0 as Real / 13.0).sqrt();
+        assert!((GranularMaterial::default().yield_stress(100.0) - cap).abs() < 1.0e-4 * cap);
+        assert!((yielded - cap).abs() < 1.0e-3 * cap);
+    }
+}
@@ -0,0 +1,251 @@
+use super::{Real, Vector};
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct SymmetricMatrix2 {
+    pub(super) xx: Real,
+    pub(super) xy: Real,
+    pub(super) yy: Real,
+}
+
+impl SymmetricMatrix2 {
+    pub(super) const ZERO: Self = Self {
+        xx: 0.0,
+        xy: 0.0,
+        yy: 0.0,
+    };
+
+    #[inline]
+    pub(super) fn symmetric_outer(a: Vector, b: Vector) -> Self {
+        Self {
+            xx: a.x * b.x,
+            xy: 0.5 * (a.x * b.y + a.y * b.x),
+            yy: a.y * b.y,
+        }
+    }
+
+    #[inline]
+    pub(super) fn trace(&self) -> Real {
+        self.xx + self.yy
+    }
+
+    #[inline]
+    pub(super) fn min_eigenvalue(&self) -> Real {
+        let mean = 0.5 * self.trace();
+        let half_difference = 0.5 * (self.xx - self.yy);
+
+        mean - (half_difference * half_difference + self.xy * self.xy).sqrt()
+    }
+
+    #[inline]
+    pub(super) fn scaled(&self, factor: Real) -> Self {
+        Self {
+            xx: self.xx * factor,
+            xy: self.xy * factor,
+            yy: self.yy * factor,
+        }
+    }
+
+    #[inline]
+    pub(super) fn transform(&self, vector: Vector) -> Vector {
+        Vector::new(
+            self.xx * vector.x + self.xy * vector.y,
+            self.xy * vector.x + self.yy * vector.y,
+        )
+    }
+
+    #[inline]
+    pub(super) fn norm(&self) -> Real {
+        (self.xx * self.xx + 2.0 * self.xy * self.xy + self.yy * self.yy).sqrt()
+    }
+}
+
+impl Add for SymmetricMatrix2 {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self {
+            xx: self.xx + other.xx,
+            xy: self.xy + other.xy,
+            yy: self.yy + other.yy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Matrix2 {
+    pub(super) xx: Real,
+    pub(super) xy: Real,
+    pub(super) yx: Real,
+    pub(super) yy: Real,
+}
+
+impl Matrix2 {
+    pub(super) const ZERO: Self = Self {
+        xx: 0.0,
+        xy: 0.0,
+        yx: 0.0,
+        yy: 0.0,
+    };
+
+    pub(super) const IDENTITY: Self = Self {
+        xx: 1.0,
+        xy: 0.0,
+        yx: 0.0,
+        yy: 1.0,
+    };
+
+    #[inline]
+    pub(super) fn outer(a: Vector, b: Vector) -> Self {
+        Self {
+            xx: a.x * b.x,
+            xy: a.x * b.y,
+            yx: a.y * b.x,
+            yy: a.y * b.y,
+        }
+    }
+
+    #[inline]
+    pub(super) fn determinant(&self) -> Real {
+        self.xx * self.yy - self.xy * self.yx
+    }
+
+    #[inline]
+    pub(super) fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant.abs() <= Real::EPSILON {
+            return None;
+        }
+
+        let inv = determinant.recip();
+        Some(Self {
+            xx: self.yy * inv,
+            xy: -self.xy * inv,
+            yx: -self.yx * inv,
+            yy: self.xx * inv,
+        })
+    }
+
+    #[inline]
+    pub(super) fn transform(&self, vector: Vector) -> Vector {
+        Vector::new(
+            self.xx * vector.x + self.xy * vector.y,
+            self.yx * vector.x + self.yy * vector.y,
+        )
+    }
+
+    #[inline]
+    pub(super) fn transpose(&self) -> Self {
+        Self {
+            xx: self.xx,
+            xy: self.yx,
+            yx: self.xy,
+            yy: self.yy,
+        }
+    }
+
+    #[inline]
+    pub(super) fn trace(&self) -> Real {
+        self.xx + self.yy
+    }
+
+    #[inline]
+    pub(super) fn scaled(&self, factor: Real) -> Self {
+        Self {
+            xx: self.xx * factor,
+            xy: self.xy * factor,
+            yx: self.yx * factor,
+            yy: self.yy * factor,
+        }
+    }
+
+    #[inline]
+    pub(super) fn symmetric_part(&self) -> SymmetricMatrix2 {
+        SymmetricMatrix2 {
+            xx: self.xx,
+            xy: 0.5 * (self.xy + self.yx),
+            yy: self.yy,
+        }
+    }
+}
+
+impl From<SymmetricMatrix2> for Matrix2 {
+    #[inline]
+    fn from(matrix: SymmetricMatrix2) -> Self {
+        Self {
+            xx: matrix.xx,
+            xy: matrix.xy,
+            yx: matrix.xy,
+            yy: matrix.yy,
+        }
+    }
+}
+
+impl Mul for Matrix2 {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: Self) -> Self {
+        Self {
+            xx: self.xx * other.xx + self.xy * other.yx,
+            xy: self.xx * other.xy + self.xy * other.yy,
+            yx: self.yx * other.xx + self.yy * other.yx,
+            yy: self.yx * other.xy + self.yy * other.yy,
+        }
+    }
+}
+
+impl Sub for Matrix2 {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        Self {
+            xx: self.xx - other.xx,
+            xy: self.xy - other.xy,
+            yx: self.yx - other.yx,
+            yy: self.yy - other.yy,
+        }
+    }
+}
+
+impl Add for Matrix2 {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self {
+            xx: self.xx + other.xx,
+            xy: self.xy + other.xy,
+            yx: self.yx + other.yx,
+            yy: self.yy + other.yy,
+        }
+    }
+}
+
+#[inline]
+pub(super) fn solve3(matrix: [[Real; 3]; 3], rhs: [Real; 3]) -> Option<[Real; 3]> {
+    let determinant = determinant3(matrix);
+    if determinant.abs() <= Real::EPSILON {
+        return None;
+    }
+
+    let mut solution = [0.0; 3];
+    for (column, value) in solution.iter_mut().enumerate() {
+        let mut replaced = matrix;
+        for (row, entry) in rhs.iter().enumerate() {
+            replaced[row][column] = *entry;
+        }
+        *value = determinant3(replaced) / determinant;
+    }
+
+    Some(solution)
+}
+
+#[inline]
+fn determinant3(m: [[Real; 3]; 3]) -> Real {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
@@ -0,0 +1,81 @@
+use super::Real;
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Phase {
+    #[default]
+    Fluid,
+    Granular,
+    Solid,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Fluid => "fluid",
+            Self::Granular => "granular",
+            Self::Solid => "solid",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GranularMaterial {
+    pub friction_angle: Real,
+    pub cohesion: Real,
+    pub shear_modulus: Real,
+}
+
+impl Default for GranularMaterial {
+    fn default() -> Self {
+        Self {
+            friction_angle: Real::to_radians(30.0),
+            cohesion: 0.0,
+            shear_modulus: 2.0e4,
+        }
+    }
+}
+
+impl GranularMaterial {
+    pub fn yield_stress(&self, pressure: Real) -> Real {
+        let tan_phi = self.friction_angle.tan();
+        let denominator = (9.0 + 12.0 * tan_phi * tan_phi).sqrt();
+
+        ((3.0 * tan_phi * pressure + 3.0 * self.cohesion) / denominator).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolidModel {
+    LinearElastic,
+    NeoHookean,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolidMaterial {
+    pub model: SolidModel,
+    pub youngs_modulus: Real,
+    pub poisson_ratio: Real,
+    pub yield_stress: Option<Real>,
+    pub hourglass_control: Real,
+}
+
+impl Default for SolidMaterial {
+    fn default() -> Self {
+        Self {
+            model: SolidModel::NeoHookean,
+            youngs_modulus: 5.0e4,
+            poisson_ratio: 0.3,
+            yield_stress: None,
+            hourglass_control: 10.0,
+        }
+    }
+}
+
+impl SolidMaterial {
+    pub fn lame_parameters(&self) -> (Real, Real) {
+        let nu = self.poisson_ratio;
+        let lambda = self.youngs_modulus * nu / ((1.0 + nu) * (1.0 - 2.0 * nu));
+        let mu = self.youngs_modulus / (2.0 * (1.0 + nu));
+
+        (lambda, mu)
+    }
+}
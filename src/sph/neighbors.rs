@@ -0,0 +1,556 @@
+use super::sort::{CountingSort, exclusive_scan};
+use super::{MIN_PAR_CHUNK, Real, SphConfig, Vector};
+use nannou::prelude::*;
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborSearchKind {
+    UniformGrid,
+    SpatialHash,
+    VerletList,
+}
+
+pub(super) trait NeighborSearch {
+    fn rebuild(&mut self, positions: &[Vector]);
+
+    fn invalidate(&mut self) {}
+
+    fn for_each_neighbor<F>(&self, particle: usize, visit: F)
+    where
+        F: FnMut(usize);
+
+    fn for_each_near<F>(&self, position: Vector, visit: F)
+    where
+        F: FnMut(usize);
+}
+
+#[derive(Clone, Debug)]
+pub(super) enum NeighborGrid {
+    Uniform(UniformGrid),
+    Hashed(SpatialHash),
+    Verlet(VerletList),
+}
+
+impl NeighborGrid {
+    pub(super) fn new(config: &SphConfig) -> Self {
+        match config.neighbor_search {
+            NeighborSearchKind::UniformGrid => Self::Uniform(UniformGrid::new(
+                config.bounds,
+                config.smoothing_radius,
+                config.parallel_grid_rebuild,
+            )),
+            NeighborSearchKind::SpatialHash => Self::Hashed(SpatialHash::new(
+                config.smoothing_radius,
+                config.parallel_grid_rebuild,
+            )),
+            NeighborSearchKind::VerletList => Self::Verlet(VerletList::new(
+                config.bounds,
+                config.smoothing_radius,
+                config.neighbor_skin,
+                config.parallel_grid_rebuild,
+            )),
+        }
+    }
+}
+
+impl NeighborSearch for NeighborGrid {
+    fn rebuild(&mut self, positions: &[Vector]) {
+        match self {
+            Self::Uniform(grid) => grid.rebuild(positions),
+            Self::Hashed(hash) => hash.rebuild(positions),
+            Self::Verlet(list) => list.rebuild(positions),
+        }
+    }
+
+    fn invalidate(&mut self) {
+        if let Self::Verlet(list) = self {
+            list.invalidate();
+        }
+    }
+
+    #[inline]
+    fn for_each_neighbor<F>(&self, particle: usize, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        match self {
+            Self::Uniform(grid) => grid.for_each_neighbor(particle, visit),
+            Self::Hashed(hash) => hash.for_each_neighbor(particle, visit),
+            Self::Verlet(list) => list.for_each_neighbor(particle, visit),
+        }
+    }
+
+    #[inline]
+    fn for_each_near<F>(&self, position: Vector, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        match self {
+            Self::Uniform(grid) => grid.for_each_near(position, visit),
+            Self::Hashed(hash) => hash.for_each_near(position, visit),
+            Self::Verlet(list) => list.for_each_near(position, visit),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct UniformGrid {
+    sorter: CountingSort,
+    cell_offsets: Vec<usize>,
+    sorted_particles: Vec<usize>,
+    pub(super) particle_cells: Vec<usize>,
+    neighbor_cells: Vec<[usize; 9]>,
+    neighbor_counts: Vec<u8>,
+    cols: usize,
+    rows: usize,
+    inv_cell_size: Real,
+    bounds_left: Real,
+    bounds_bottom: Real,
+}
+
+impl UniformGrid {
+    pub(super) fn new(bounds: Rect<Real>, cell_size: Real, parallel: bool) -> Self {
+        let cols = (bounds.w() / cell_size).ceil().max(1.0) as usize + 1;
+        let rows = (bounds.h() / cell_size).ceil().max(1.0) as usize + 1;
+        let cell_total = cols * rows;
+        let mut neighbor_cells = vec![[0; 9]; cell_total];
+        let mut neighbor_counts = vec![0; cell_total];
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let cell_index = row * cols + col;
+                let min_row = row.saturating_sub(1);
+                let max_row = (row + 1).min(rows - 1);
+                let min_col = col.saturating_sub(1);
+                let max_col = (col + 1).min(cols - 1);
+                let mut count = 0;
+
+                for current_row in min_row..=max_row {
+                    for current_col in min_col..=max_col {
+                        neighbor_cells[cell_index][count] = current_row * cols + current_col;
+                        count += 1;
+                    }
+                }
+
+                neighbor_counts[cell_index] = count as u8;
+            }
+        }
+
+        Self {
+            sorter: CountingSort::new(parallel),
+            cell_offsets: vec![0; cell_total + 1],
+            sorted_particles: Vec::new(),
+            particle_cells: Vec::new(),
+            neighbor_cells,
+            neighbor_counts,
+            cols,
+            rows,
+            inv_cell_size: cell_size.recip(),
+            bounds_left: bounds.left(),
+            bounds_bottom: bounds.bottom(),
+        }
+    }
+
+    fn sort(&mut self, positions: &[Vector]) {
+        if self.sorted_particles.len() != positions.len() {
+            self.sorted_particles.resize(positions.len(), 0);
+            self.particle_cells.resize(positions.len(), 0);
+        }
+
+        let mut particle_cells = std::mem::take(&mut self.particle_cells);
+        particle_cells
+            .par_iter_mut()
+            .zip(positions.par_iter())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(cell, position)| *cell = self.cell_index(*position));
+        self.particle_cells = particle_cells;
+
+        self.sorter.sort(
+            &self.particle_cells,
+            &mut self.cell_offsets,
+            &mut self.sorted_particles,
+        );
+    }
+
+    #[inline]
+    fn for_each_in_block<F>(&self, particle_cell: usize, mut visit: F)
+    where
+        F: FnMut(usize),
+    {
+        let cells = &self.neighbor_cells[particle_cell];
+        let count = self.neighbor_counts[particle_cell] as usize;
+
+        for &cell in &cells[..count] {
+            let start = self.cell_offsets[cell];
+            let end = self.cell_offsets[cell + 1];
+
+            for slot in start..end {
+                visit(self.sorted_particles[slot]);
+            }
+        }
+    }
+
+    #[inline]
+    fn cell_index(&self, position: Vector) -> usize {
+        let col = ((position.x - self.bounds_left) * self.inv_cell_size).floor() as isize;
+        let row = ((position.y - self.bounds_bottom) * self.inv_cell_size).floor() as isize;
+        let col = col.clamp(0, self.cols as isize - 1) as usize;
+        let row = row.clamp(0, self.rows as isize - 1) as usize;
+
+        row * self.cols + col
+    }
+}
+
+impl NeighborSearch for UniformGrid {
+    fn rebuild(&mut self, positions: &[Vector]) {
+        self.sort(positions);
+    }
+
+    #[inline]
+    fn for_each_neighbor<F>(&self, particle: usize, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        self.for_each_in_block(self.particle_cells[particle], visit);
+    }
+
+    #[inline]
+    fn for_each_near<F>(&self, position: Vector, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        self.for_each_in_block(self.cell_index(position), visit);
+    }
+}
+
+const HASH_PRIME_X: u32 = 73_856_093;
+const HASH_PRIME_Y: u32 = 19_349_663;
+const MIN_HASH_TABLE: usize = 64;
+
+#[derive(Clone, Debug)]
+pub(super) struct SpatialHash {
+    sorter: CountingSort,
+    bucket_offsets: Vec<usize>,
+    sorted_particles: Vec<usize>,
+    particle_coords: Vec<(i32, i32)>,
+    particle_buckets: Vec<usize>,
+    table_mask: usize,
+    inv_cell_size: Real,
+}
+
+impl SpatialHash {
+    pub(super) fn new(cell_size: Real, parallel: bool) -> Self {
+        Self {
+            sorter: CountingSort::new(parallel),
+            bucket_offsets: vec![0; MIN_HASH_TABLE + 1],
+            sorted_particles: Vec::new(),
+            particle_coords: Vec::new(),
+            particle_buckets: Vec::new(),
+            table_mask: MIN_HASH_TABLE - 1,
+            inv_cell_size: cell_size.recip(),
+        }
+    }
+
+    #[inline]
+    fn cell_coords(&self, position: Vector) -> (i32, i32) {
+        (
+            (position.x * self.inv_cell_size).floor() as i32,
+            (position.y * self.inv_cell_size).floor() as i32,
+        )
+    }
+
+    #[inline]
+    fn bucket(&self, (col, row): (i32, i32)) -> usize {
+        ((col as u32).wrapping_mul(HASH_PRIME_X) ^ (row as u32).wrapping_mul(HASH_PRIME_Y)) as usize
+            & self.table_mask
+    }
+
+    #[inline]
+    fn for_each_in_block<F>(&self, (col, row): (i32, i32), mut visit: F)
+    where
+        F: FnMut(usize),
+    {
+        let mut visited = [usize::MAX; 9];
+        let mut count = 0;
+
+        for row_offset in -1..=1 {
+            for col_offset in -1..=1 {
+                let bucket =
+                    self.bucket((col.wrapping_add(col_offset), row.wrapping_add(row_offset)));
+                if visited[..count].contains(&bucket) {
+                    continue;
+                }
+                visited[count] = bucket;
+                count += 1;
+
+                for slot in self.bucket_offsets[bucket]..self.bucket_offsets[bucket + 1] {
+                    visit(self.sorted_particles[slot]);
+                }
+            }
+        }
+    }
+}
+
+impl NeighborSearch for SpatialHash {
+    fn rebuild(&mut self, positions: &[Vector]) {
+        let table_size = (2 * positions.len())
+            .next_power_of_two()
+            .max(MIN_HASH_TABLE);
+        if table_size + 1 != self.bucket_offsets.len() {
+            self.bucket_offsets.resize(table_size + 1, 0);
+            self.table_mask = table_size - 1;
+        }
+        if self.sorted_particles.len() != positions.len() {
+            self.sorted_particles.resize(positions.len(), 0);
+            self.particle_coords.resize(positions.len(), (0, 0));
+            self.particle_buckets.resize(positions.len(), 0);
+        }
+
+        let mut particle_coords = std::mem::take(&mut self.particle_coords);
+        let mut particle_buckets = std::mem::take(&mut self.particle_buckets);
+        particle_coords
+            .par_iter_mut()
+            .zip(particle_buckets.par_iter_mut())
+            .zip(positions.par_iter())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|((coords, bucket), position)| {
+                *coords = self.cell_coords(*position);
+                *bucket = self.bucket(*coords);
+            });
+        self.particle_coords = particle_coords;
+        self.particle_buckets = particle_buckets;
+
+        self.sorter.sort(
+            &self.particle_buckets,
+            &mut self.bucket_offsets,
+            &mut self.sorted_particles,
+        );
+    }
+
+    #[inline]
+    fn for_each_neighbor<F>(&self, particle: usize, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        self.for_each_in_block(self.particle_coords[particle], visit);
+    }
+
+    #[inline]
+    fn for_each_near<F>(&self, position: Vector, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        self.for_each_in_block(self.cell_coords(position), visit);
+    }
+}
+
+const VERLET_BLOCK: usize = 256;
+
+#[derive(Clone, Debug)]
+pub(super) struct VerletList {
+    grid: UniformGrid,
+    reference_positions: Vec<Vector>,
+    neighbor_offsets: Vec<usize>,
+    neighbors: Vec<usize>,
+    list_radius_sq: Real,
+    half_skin_sq: Real,
+    stale: bool,
+}
+
+impl VerletList {
+    pub(super) fn new(
+        bounds: Rect<Real>,
+        smoothing_radius: Real,
+        skin: Real,
+        parallel: bool,
+    ) -> Self {
+        let list_radius = smoothing_radius + skin;
+
+        Self {
+            grid: UniformGrid::new(bounds, list_radius, parallel),
+            reference_positions: Vec::new(),
+            neighbor_offsets: vec![0],
+            neighbors: Vec::new(),
+            list_radius_sq: list_radius * list_radius,
+            half_skin_sq: 0.25 * skin * skin,
+            stale: true,
+        }
+    }
+
+    fn needs_rebuild(&self, positions: &[Vector]) -> bool {
+        self.stale
+            || self.reference_positions.len() != positions.len()
+            || positions
+                .par_iter()
+                .zip(self.reference_positions.par_iter())
+                .with_min_len(MIN_PAR_CHUNK)
+                .any(|(position, reference)| {
+                    position.distance_squared(*reference) > self.half_skin_sq
+                })
+    }
+
+    fn build_lists(&mut self, positions: &[Vector]) {
+        let grid = &self.grid;
+        let list_radius_sq = self.list_radius_sq;
+        let within = |particle: usize, neighbor: usize| {
+            positions[particle].distance_squared(positions[neighbor]) < list_radius_sq
+        };
+
+        self.neighbor_offsets.resize(positions.len() + 1, 0);
+        self.neighbor_offsets[..positions.len()]
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(particle, count)| {
+                *count = 0;
+                grid.for_each_neighbor(particle, |neighbor| {
+                    if within(particle, neighbor) {
+                        *count += 1;
+                    }
+                });
+            });
+        let total = exclusive_scan(&mut self.neighbor_offsets[..positions.len()]);
+        self.neighbor_offsets[positions.len()] = total;
+        self.neighbors.resize(total, 0);
+
+        let offsets = &self.neighbor_offsets;
+        let mut rest = &mut self.neighbors[..];
+        let mut blocks = Vec::with_capacity(positions.len().div_ceil(VERLET_BLOCK));
+        for start in (0..positions.len()).step_by(VERLET_BLOCK) {
+            let end = (start + VERLET_BLOCK).min(positions.len());
+            let (block, tail) =
+                std::mem::take(&mut rest).split_at_mut(offsets[end] - offsets[start]);
+            blocks.push((start..end, block));
+            rest = tail;
+        }
+
+        blocks.into_par_iter().for_each(|(particles, block)| {
+            let mut cursor = 0;
+            for particle in particles {
+                grid.for_each_neighbor(particle, |neighbor| {
+                    if within(particle, neighbor) {
+                        block[cursor] = neighbor;
+                        cursor += 1;
+                    }
+                });
+            }
+        });
+    }
+}
+
+impl NeighborSearch for VerletList {
+    fn rebuild(&mut self, positions: &[Vector]) {
+        if !self.needs_rebuild(positions) {
+            return;
+        }
+
+        self.grid.rebuild(positions);
+        self.build_lists(positions);
+        self.reference_positions.clear();
+        self.reference_positions.extend_from_slice(positions);
+        self.stale = false;
+    }
+
+    fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    #[inline]
+    fn for_each_neighbor<F>(&self, particle: usize, mut visit: F)
+    where
+        F: FnMut(usize),
+    {
+        let start = self.neighbor_offsets[particle];
+        let end = self.neighbor_offsets[particle + 1];
+
+        for &neighbor in &self.neighbors[start..end] {
+            visit(neighbor);
+        }
+    }
+
+    #[inline]
+    fn for_each_near<F>(&self, position: Vector, visit: F)
+    where
+        F: FnMut(usize),
+    {
+        self.grid.for_each_near(position, visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_queries_only_local_cells() {
+        let bounds = Rect::from_w_h(1.0, 1.0);
+        let mut grid = UniformGrid::new(bounds, 0.1, true);
+        let positions = vec![
+            Vector::new(-0.25, -0.25),
+            Vector::new(-0.21, -0.21),
+            Vector::new(0.35, 0.35),
+        ];
+
+        grid.rebuild(&positions);
+
+        let mut hits = Vec::new();
+        grid.for_each_neighbor(0, |index| hits.push(index));
+
+        assert!(hits.contains(&0));
+        assert!(hits.contains(&1));
+        assert!(!hits.contains(&2));
+    }
+
+    #[test]
+    fn spatial_hash_finds_neighbors_far_outside_the_bounds() {
+        let mut hash = SpatialHash::new(0.1, true);
+        let positions = vec![
+            Vector::new(-0.25, -0.25),
+            Vector::new(-0.21, -0.21),
+            Vector::new(1.0e4, -3.0e3),
+            Vector::new(1.0e4 + 0.05, -3.0e3),
+        ];
+
+        hash.rebuild(&positions);
+
+        let mut near_origin = Vec::new();
+        hash.for_each_neighbor(0, |index| near_origin.push(index));
+        near_origin.sort_unstable();
+        assert_eq!(near_origin, [0, 1]);
+
+        let mut escaped = Vec::new();
+        hash.for_each_near(Vector::new(1.0e4 + 0.02, -3.0e3), |index| {
+            escaped.push(index)
+        });
+        escaped.sort_unstable();
+        assert_eq!(escaped, [2, 3]);
+    }
+
+    #[test]
+    fn verlet_list_is_reused_until_a_particle_leaves_the_skin() {
+        let bounds = Rect::from_w_h(1.0, 1.0);
+        let mut list = VerletList::new(bounds, 0.1, 0.02, true);
+        let mut positions = vec![
+            Vector::new(0.0, 0.0),
+            Vector::new(0.11, 0.0),
+            Vector::new(0.3, 0.0),
+        ];
+
+        list.rebuild(&positions);
+        let mut hits = Vec::new();
+        list.for_each_neighbor(0, |index| hits.push(index));
+        assert_eq!(hits, [0, 1]);
+
+        positions[2].x -= 0.009;
+        assert!(!list.needs_rebuild(&positions));
+
+        positions[2].x -= 0.002;
+        assert!(list.needs_rebuild(&positions));
+
+        positions[1].x = 0.5;
+        list.rebuild(&positions);
+        hits.clear();
+        list.for_each_neighbor(0, |index| hits.push(index));
+        assert_eq!(hits, [0]);
+    }
+}
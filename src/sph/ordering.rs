@@ -0,0 +1,186 @@
+use super::{MIN_PAR_CHUNK, NeighborSearch, Real, SphSimulation, Vector};
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParticleOrdering {
+    #[default]
+    None,
+    Cell,
+    Morton,
+}
+
+impl SphSimulation {
+    pub fn particle_ids(&self) -> &[u32] {
+        &self.particle_ids
+    }
+
+    pub fn particle_slot(&self, id: u32) -> Option<usize> {
+        self.id_slots.get(id as usize).map(|slot| *slot as usize)
+    }
+
+    pub fn reorder_particles(&mut self) {
+        let ordering = self.config.particle_ordering;
+        if ordering == ParticleOrdering::None || self.positions.is_empty() {
+            return;
+        }
+
+        let keys = ordering_keys(ordering, &self.positions, self.config.smoothing_radius);
+        let mut order = (0..self.positions.len()).collect::<Vec<_>>();
+        order.par_sort_by_key(|index| keys[*index]);
+        if order.iter().enumerate().all(|(slot, index)| slot == *index) {
+            return;
+        }
+
+        permute(&mut self.positions, &order);
+        permute(&mut self.velocities, &order);
+        permute(&mut self.densities, &order);
+        permute(&mut self.density_rates, &order);
+        permute(&mut self.density_gradients, &order);
+        permute(&mut self.inv_densities, &order);
+        permute(&mut self.pressures, &order);
+        permute(&mut self.pressure_terms, &order);
+        permute(&mut self.phases, &order);
+        permute(&mut self.deviatoric_stresses, &order);
+        permute(&mut self.pinned, &order);
+        permute(&mut self.porous_indices, &order);
+        permute(&mut self.gradient_corrections, &order);
+        permute(&mut self.accelerations, &order);
+        permute(&mut self.xsph_corrections, &order);
+        permute(&mut self.shifts, &order);
+        permute(&mut self.dissipative_terms, &order);
+        permute(&mut self.classes, &order);
+        permute(&mut self.surface_normals, &order);
+        permute(&mut self.vorticities, &order);
+        permute(&mut self.particle_ids, &order);
+
+        let mut new_slots = vec![0; order.len()];
+        for (slot, index) in order.iter().enumerate() {
+            new_slots[*index] = slot;
+        }
+        self.solids.remap_particles(&new_slots);
+        self.grid.invalidate();
+        self.neighbors_stale = true;
+
+        for (slot, id) in self.particle_ids.iter().enumerate() {
+            self.id_slots[*id as usize] = slot as u32;
+        }
+        self.stats_dirty = true;
+    }
+}
+
+fn ordering_keys(ordering: ParticleOrdering, positions: &[Vector], cell_size: Real) -> Vec<u64> {
+    let origin = positions
+        .iter()
+        .fold(Vector::splat(Real::MAX), |origin, position| {
+            origin.min(*position)
+        });
+    let inv_cell_size = cell_size.recip();
+    let cell = |position: Vector| {
+        let coords = ((position - origin) * inv_cell_size).floor();
+        (coords.x as u32, coords.y as u32)
+    };
+    let cols = positions
+        .iter()
+        .map(|position| cell(*position).0 as u64 + 1)
+        .max()
+        .unwrap_or(1);
+
+    positions
+        .par_iter()
+        .with_min_len(MIN_PAR_CHUNK)
+        .map(|position| {
+            let (col, row) = cell(*position);
+            match ordering {
+                ParticleOrdering::Cell => row as u64 * cols + col as u64,
+                ParticleOrdering::Morton | ParticleOrdering::None => {
+                    spread_bits(col) | spread_bits(row) << 1
+                }
+            }
+        })
+        .collect()
+}
+
+fn spread_bits(value: u32) -> u64 {
+    let mut bits = value as u64;
+    bits = (bits | bits << 16) & 0x0000_ffff_0000_ffff;
+    bits = (bits | bits << 8) & 0x00ff_00ff_00ff_00ff;
+    bits = (bits | bits << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    bits = (bits | bits << 2) & 0x3333_3333_3333_3333;
+    (bits | bits << 1) & 0x5555_5555_5555_5555
+}
+
+fn permute<T: Copy + Send + Sync>(values: &mut Vec<T>, order: &[usize]) {
+    let permuted = order
+        .par_iter()
+        .with_min_len(MIN_PAR_CHUNK)
+        .map(|index| values[*index])
+        .collect();
+    *values = permuted;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn reordering_keeps_ids_attached_to_their_parcels() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.particle_ordering = ParticleOrdering::Morton;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(24, 12, Vector::new(-0.8, -0.4));
+        for (velocity, position) in simulation.velocities.iter_mut().zip(&simulation.positions) {
+            *velocity = Vector::new(position.y, -position.x);
+        }
+        let before = simulation.positions.clone();
+
+        simulation.reorder_particles();
+
+        assert_ne!(simulation.positions, before);
+        for (id, position) in before.iter().enumerate() {
+            let slot = simulation.particle_slot(id as u32).unwrap();
+            assert_eq!(simulation.particle_ids[slot], id as u32);
+            assert_eq!(simulation.positions[slot], *position);
+            assert_eq!(
+                simulation.velocities[slot],
+                Vector::new(position.y, -position.x)
+            );
+        }
+
+        let keys = ordering_keys(
+            ParticleOrdering::Morton,
+            &simulation.positions,
+            config.smoothing_radius,
+        );
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn refreshes_after_reordering_match_an_unreordered_run() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut plain = SphSimulation::new(config);
+        plain.seed_block(24, 12, Vector::new(-0.8, -0.4));
+        for _ in 0..3 {
+            plain.step(1.0 / 240.0, None);
+        }
+        plain.rebuild_neighbors();
+        let mut reordered = plain.clone();
+        reordered.config.particle_ordering = ParticleOrdering::Morton;
+
+        reordered.reorder_particles();
+        for simulation in [&mut plain, &mut reordered] {
+            simulation.refresh_classes();
+            simulation.refresh_vorticities();
+        }
+
+        for (slot, id) in plain.particle_ids.iter().enumerate() {
+            let other = reordered.particle_slot(*id).unwrap();
+            assert_eq!(plain.classes[slot], reordered.classes[other]);
+            assert!((plain.vorticities[slot] - reordered.vorticities[other]).abs() < 1.0e-3);
+            assert!(
+                (plain.surface_normals[slot] - reordered.surface_normals[other]).length() < 1.0e-3
+            );
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::{MIN_PAR_CHUNK, Real, SphConfig, SphSimulation, Vector};
+use nannou::prelude::*;
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum PorousShape {
+    Rect(Rect<Real>),
+    Circle { center: Vector, radius: Real },
+    Sdf(fn(Vector) -> Real),
+}
+
+impl PorousShape {
+    pub fn contains(&self, position: Vector) -> bool {
+        match *self {
+            Self::Rect(rect) => rect.contains_point(position.to_array()),
+            Self::Circle { center, radius } => position.distance_squared(center) <= radius * radius,
+            Self::Sdf(distance) => distance(position) <= 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PorousZone {
+    pub shape: PorousShape,
+    pub porosity: Real,
+    pub permeability: Real,
+    pub forchheimer: Real,
+    pub volume_correction: bool,
+}
+
+impl PorousZone {
+    pub fn new(shape: PorousShape, porosity: Real, permeability: Real) -> Self {
+        Self {
+            shape,
+            porosity,
+            permeability,
+            forchheimer: 0.55,
+            volume_correction: false,
+        }
+    }
+
+    #[inline]
+    fn drag(&self, config: SphConfig, velocity: Vector) -> Vector {
+        let permeability = self.permeability.max(Real::EPSILON);
+        let darcy = self.porosity * config.fluid_viscosity / permeability;
+        let forchheimer =
+            self.porosity * self.forchheimer / permeability.sqrt() * velocity.length();
+
+        -(darcy + forchheimer) * velocity
+    }
+}
+
+impl SphSimulation {
+    pub fn add_porous_zone(&mut self, zone: PorousZone) {
+        self.porous_zones.push(zone);
+    }
+
+    pub fn clear_porous_zones(&mut self) {
+        self.porous_zones.clear();
+        self.porous_indices.fill(None);
+    }
+
+    pub fn porous_zones(&self) -> &[PorousZone] {
+        &self.porous_zones
+    }
+
+    pub(super) fn locate_porous_zones(&mut self) {
+        let zones = &self.porous_zones;
+
+        self.porous_indices
+            .par_iter_mut()
+            .zip(self.positions.par_iter())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(zone_index, position)| {
+                *zone_index = zones.iter().position(|zone| zone.shape.contains(*position));
+            });
+    }
+
+    #[inline]
+    pub(super) fn porous_drag(
+        config: SphConfig,
+        zones: &[PorousZone],
+        zone_index: Option<usize>,
+        velocity: Vector,
+    ) -> Vector {
+        zone_index.map_or(Vector::ZERO, |index| zones[index].drag(config, velocity))
+    }
+
+    #[inline]
+    pub(super) fn intrinsic_density(
+        zones: &[PorousZone],
+        zone_index: Option<usize>,
+        density: Real,
+    ) -> Real {
+        match zone_index.map(|index| zones[index]) {
+            Some(zone) if zone.volume_correction => density / zone.porosity.max(Real::EPSILON),
+            _ => density,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn left_of_diagonal(position: Vector) -> Real {
+        position.x - position.y
+    }
+
+    #[test]
+    fn drag_only_acts_inside_zones_and_grows_with_speed() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(3, 1, Vector::new(-1.0, -0.5));
+        simulation.add_porous_zone(PorousZone::new(
+            PorousShape::Circle {
+                center: Vector::new(-1.0, -0.5),
+                radius: 0.01,
+            },
+            0.4,
+            1.0e-3,
+        ));
+        simulation.add_porous_zone(PorousZone::new(
+            PorousShape::Sdf(left_of_diagonal),
+            0.4,
+            1.0e-3,
+        ));
+        simulation.locate_porous_zones();
+
+        assert_eq!(simulation.porous_indices[..3], [Some(0), Some(1), Some(1)]);
+
+        simulation.positions[1] = Vector::new(1.0, -1.0);
+        simulation.locate_porous_zones();
+        assert_eq!(simulation.porous_indices[1], None);
+
+        let zones = simulation.porous_zones();
+        let slow = SphSimulation::porous_drag(config, zones, Some(0), Vector::new(0.1, 0.0));
+        let fast = SphSimulation::porous_drag(config, zones, Some(0), Vector::new(1.0, 0.0));
+        assert!(slow.x < 0.0 && fast.x < 10.0 * slow.x);
+
+        let mut viscous = config;
+        viscous.fluid_viscosity = 1.0e-3;
+        let darcy = SphSimulation::porous_drag(viscous, zones, Some(0), Vector::new(1.0e-3, 0.0));
+        let expected = -0.4 * 1.0e-3 / 1.0e-3 * 1.0e-3;
+        assert!((darcy.x - expected).abs() < 0.1 * expected.abs());
+        assert_eq!(
+            SphSimulation::porous_drag(config, zones, None, Vector::new(1.0, 0.0)),
+            Vector::ZERO
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Instant;
+
+const TIMING_SMOOTHING: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhaseTimings {
+    pub reorder: f32,
+    pub neighbors: f32,
+    pub density: f32,
+    pub porous: f32,
+    pub pressure: f32,
+    pub correction: f32,
+    pub granular: f32,
+    pub classification: f32,
+    pub vorticity: f32,
+    pub accelerations: f32,
+    pub solids: f32,
+    pub integrate: f32,
+}
+
+impl PhaseTimings {
+    pub fn total(&self) -> f32 {
+        self.entries().iter().map(|(_, millis)| millis).sum()
+    }
+
+    pub fn entries(&self) -> [(&'static str, f32); 12] {
+        [
+            ("reorder", self.reorder),
+            ("neighbors", self.neighbors),
+            ("density", self.density),
+            ("porous", self.porous),
+            ("pressure", self.pressure),
+            ("correction", self.correction),
+            ("granular", self.granular),
+            ("classification", self.classification),
+            ("vorticity", self.vorticity),
+            ("accelerations", self.accelerations),
+            ("solids", self.solids),
+            ("integrate", self.integrate),
+        ]
+    }
+
+    pub(super) fn blend(&mut self, sample: Self, first: bool) {
+        let weight = if first { 1.0 } else { TIMING_SMOOTHING };
+        let mix = |average: &mut f32, value: f32| *average += weight * (value - *average);
+
+        mix(&mut self.reorder, sample.reorder);
+        mix(&mut self.neighbors, sample.neighbors);
+        mix(&mut self.density, sample.density);
+        mix(&mut self.porous, sample.porous);
+        mix(&mut self.pressure, sample.pressure);
+        mix(&mut self.correction, sample.correction);
+        mix(&mut self.granular, sample.granular);
+        mix(&mut self.classification, sample.classification);
+        mix(&mut self.vorticity, sample.vorticity);
+        mix(&mut self.accelerations, sample.accelerations);
+        mix(&mut self.solids, sample.solids);
+        mix(&mut self.integrate, sample.integrate);
+    }
+}
+
+pub(super) struct PhaseTimer {
+    last: Instant,
+}
+
+impl PhaseTimer {
+    pub(super) fn start() -> Self {
+        Self {
+            last: Instant::now(),
+        }
+    }
+
+    pub(super) fn lap(&mut self) -> f32 {
+        let now = Instant::now();
+        let millis = (now - self.last).as_secs_f32() * 1.0e3;
+        self.last = now;
+        millis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_average_starts_at_the_first_sample_and_smooths_later_ones() {
+        let mut timings = PhaseTimings::default();
+        let sample = PhaseTimings {
+            density: 2.0,
+            accelerations: 4.0,
+            ..PhaseTimings::default()
+        };
+
+        timings.blend(sample, true);
+        assert_eq!(timings.density, 2.0);
+        assert_eq!(timings.total(), 6.0);
+
+        timings.blend(PhaseTimings::default(), false);
+        assert!((timings.density - 2.0 * (1.0 - TIMING_SMOOTHING)).abs() < 1.0e-6);
+    }
+}
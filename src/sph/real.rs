@@ -0,0 +1,101 @@
+use nannou::prelude::*;
+
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+#[cfg(not(feature = "f64"))]
+pub type Vector = Vec2;
+#[cfg(feature = "f64")]
+pub type Vector = DVec2;
+
+#[cfg(not(feature = "f64"))]
+pub type Vector3 = Vec3;
+#[cfg(feature = "f64")]
+pub type Vector3 = DVec3;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+#[cfg(not(feature = "f64"))]
+pub(super) const NPY_DESCR: &str = "<f4";
+#[cfg(feature = "f64")]
+pub(super) const NPY_DESCR: &str = "<f8";
+
+#[cfg(not(feature = "f64"))]
+pub(super) const VTK_FLOAT: &str = "Float32";
+#[cfg(feature = "f64")]
+pub(super) const VTK_FLOAT: &str = "Float64";
+
+#[cfg(not(feature = "f64"))]
+pub fn to_f32(value: Real) -> f32 {
+    value
+}
+
+#[cfg(feature = "f64")]
+pub fn to_f32(value: Real) -> f32 {
+    value as f32
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn from_f32(value: f32) -> Real {
+    value
+}
+
+#[cfg(feature = "f64")]
+pub fn from_f32(value: f32) -> Real {
+    value as Real
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn to_vec2(vector: Vector) -> Vec2 {
+    vector
+}
+
+#[cfg(feature = "f64")]
+pub fn to_vec2(vector: Vector) -> Vec2 {
+    vec2(vector.x as f32, vector.y as f32)
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn from_vec2(vector: Vec2) -> Vector {
+    vector
+}
+
+#[cfg(feature = "f64")]
+pub fn from_vec2(vector: Vec2) -> Vector {
+    Vector::new(vector.x as Real, vector.y as Real)
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn to_rect(rect: Rect<Real>) -> Rect {
+    rect
+}
+
+#[cfg(feature = "f64")]
+pub fn to_rect(rect: Rect<Real>) -> Rect {
+    Rect::<f32>::from_x_y_w_h(
+        rect.x() as f32,
+        rect.y() as f32,
+        rect.w() as f32,
+        rect.h() as f32,
+    )
+}
+
+#[cfg(feature = "f64")]
+pub fn from_rect(rect: Rect) -> Rect<Real> {
+    Rect::<Real>::from_x_y_w_h(
+        rect.x() as Real,
+        rect.y() as Real,
+        rect.w() as Real,
+        rect.h() as Real,
+    )
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn from_rect(rect: Rect) -> Rect<Real> {
+    rect
+}
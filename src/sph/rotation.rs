@@ -0,0 +1,72 @@
+use super::{Real, SphConfig, SphSimulation, Vector};
+
+impl SphSimulation {
+    pub fn inertial_velocities(&self) -> Vec<Vector> {
+        self.positions
+            .iter()
+            .zip(&self.velocities)
+            .map(|(position, velocity)| Self::inertial_velocity(self.config, *position, *velocity))
+            .collect()
+    }
+
+    #[inline]
+    pub(super) fn reported_velocity(&self, index: usize) -> Vector {
+        let velocity = self.velocities[index];
+        if self.config.report_inertial_velocities {
+            Self::inertial_velocity(self.config, self.positions[index], velocity)
+        } else {
+            velocity
+        }
+    }
+
+    #[inline]
+    pub(super) fn inertial_velocity(
+        config: SphConfig,
+        position: Vector,
+        velocity: Vector,
+    ) -> Vector {
+        velocity + config.frame_angular_velocity * (position - config.frame_center).perp()
+    }
+
+    #[inline]
+    pub(super) fn frame_acceleration(
+        config: SphConfig,
+        position: Vector,
+        velocity: Vector,
+    ) -> Vector {
+        let omega = config.frame_angular_velocity;
+        let coriolis = -2.0 * omega * velocity.perp();
+        let centrifugal = omega * omega * (position - config.frame_center);
+
+        coriolis + centrifugal
+    }
+
+    #[inline]
+    pub(super) fn centrifugal_potential(config: SphConfig, position: Vector) -> Real {
+        let omega = config.frame_angular_velocity;
+        -0.5 * omega * omega * position.distance_squared(config.frame_center)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn rigid_rotation_is_at_rest_in_the_rotating_frame() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.frame_angular_velocity = 2.0;
+        config.frame_center = Vector::new(0.5, 0.0);
+        let position = Vector::new(1.5, 0.0);
+
+        let inertial = SphSimulation::inertial_velocity(config, position, Vector::ZERO);
+        assert_eq!(inertial, Vector::new(0.0, 2.0));
+
+        let at_rest = SphSimulation::frame_acceleration(config, position, Vector::ZERO);
+        assert_eq!(at_rest, Vector::new(4.0, 0.0));
+
+        let moving_out = SphSimulation::frame_acceleration(config, position, Vector::new(1.0, 0.0));
+        assert!(moving_out.y < 0.0);
+    }
+}
@@ -0,0 +1,115 @@
+use super::{
+    EPSILON, MIN_PAR_CHUNK, NeighborSearch, ParticleClass, Phase, Real, SphSimulation, Vector,
+    clamp_magnitude,
+};
+use rayon::prelude::*;
+
+const TENSILE_COEFFICIENT: Real = 0.2;
+const TENSILE_EXPONENT: i32 = 4;
+
+impl SphSimulation {
+    pub(super) fn shift_particles(&mut self, dt: Real) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let velocities = &self.velocities;
+        let inv_densities = &self.inv_densities;
+        let classes = &self.classes;
+        let phases = &self.phases;
+        let surface_normals = &self.surface_normals;
+        let grid = &self.grid;
+        let reference_weight = kernels
+            .poly6(config.particle_spacing * config.particle_spacing)
+            .max(EPSILON);
+
+        self.shifts
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, shift)| {
+                *shift = Vector::ZERO;
+                if classes[index] == ParticleClass::Isolated || phases[index] == Phase::Solid {
+                    return;
+                }
+
+                let position_i = positions[index];
+                let mut concentration_gradient = Vector::ZERO;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let ratio = kernels.poly6(distance_sq) / reference_weight;
+                    let tensile = 1.0 + TENSILE_COEFFICIENT * ratio.powi(TENSILE_EXPONENT);
+                    concentration_gradient += config.particle_mass
+                        * inv_densities[neighbor]
+                        * tensile
+                        * kernels.spiky_gradient(delta, distance_sq.sqrt());
+                });
+
+                let diffusion = config.shifting_coefficient
+                    * config.smoothing_radius
+                    * velocities[index].length()
+                    * dt;
+                let mut value = -diffusion * concentration_gradient;
+
+                let normal = surface_normals[index];
+                if classes[index] == ParticleClass::FreeSurface && normal.length_squared() > EPSILON
+                {
+                    let normal = normal.normalize();
+                    value -= value.dot(normal) * normal;
+                }
+
+                *shift = clamp_magnitude(value, config.shifting_max_distance);
+            });
+
+        let min =
+            Vector::new(config.bounds.left(), config.bounds.bottom()) + config.boundary_margin;
+        let max = Vector::new(config.bounds.right(), config.bounds.top()) - config.boundary_margin;
+        self.positions
+            .par_iter_mut()
+            .zip(self.shifts.par_iter().copied())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(position, shift)| {
+                *position = (*position + shift).clamp(min, max);
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn shifting_spreads_a_clump_but_keeps_the_surface() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.shifting_coefficient = 2.0;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        let clumped = 10 * 20 + 10;
+        simulation.positions[clumped] += Vector::new(0.4, 0.0) * config.particle_spacing;
+        for velocity in &mut simulation.velocities {
+            *velocity = Vector::new(0.0, 1.0);
+        }
+
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+        simulation.classify_particles();
+        simulation.shift_particles(1.0 / 240.0);
+
+        let clump = simulation.shifts[clumped];
+        assert!(clump.x < 0.0);
+        assert!(clump.x.abs() > 10.0 * clump.y.abs());
+
+        let surface = 19 * 20 + 10;
+        assert_eq!(simulation.classes[surface], ParticleClass::FreeSurface);
+        assert!(simulation.shifts[surface].y.abs() < 1.0e-2 * clump.length());
+    }
+}
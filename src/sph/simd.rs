@@ -0,0 +1,189 @@
+use super::{KernelSet, Real, Vector};
+
+pub(super) const LANES: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct PairKernel {
+    pub(super) neighbor: usize,
+    pub(super) delta: Vector,
+    pub(super) relative_velocity: Vector,
+    pub(super) distance_sq: Real,
+    pub(super) distance: Real,
+    pub(super) poly6: Real,
+    pub(super) gradient: Vector,
+    pub(super) laplacian: Real,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct KernelLanes {
+    pub(super) distance_sq: [Real; LANES],
+    pub(super) distance: [Real; LANES],
+    pub(super) poly6: [Real; LANES],
+    pub(super) gradient_scale: [Real; LANES],
+    pub(super) laplacian: [Real; LANES],
+}
+
+#[derive(Debug)]
+pub(super) struct NeighborBatch {
+    neighbors: [usize; LANES],
+    dx: [Real; LANES],
+    dy: [Real; LANES],
+    vx: [Real; LANES],
+    vy: [Real; LANES],
+    len: usize,
+}
+
+impl NeighborBatch {
+    pub(super) fn new() -> Self {
+        Self {
+            neighbors: [0; LANES],
+            dx: [0.0; LANES],
+            dy: [0.0; LANES],
+            vx: [0.0; LANES],
+            vy: [0.0; LANES],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub(super) fn push(
+        &mut self,
+        neighbor: usize,
+        delta: Vector,
+        relative_velocity: Vector,
+    ) -> bool {
+        self.neighbors[self.len] = neighbor;
+        self.dx[self.len] = delta.x;
+        self.dy[self.len] = delta.y;
+        self.vx[self.len] = relative_velocity.x;
+        self.vy[self.len] = relative_velocity.y;
+        self.len += 1;
+        self.len == LANES
+    }
+
+    #[inline]
+    pub(super) fn flush<F>(&mut self, kernels: &KernelSet, mut visit: F)
+    where
+        F: FnMut(PairKernel),
+    {
+        if self.len == 0 {
+            return;
+        }
+
+        let lanes = kernels.evaluate_lanes(&self.dx, &self.dy);
+        for lane in 0..self.len {
+            let delta = Vector::new(self.dx[lane], self.dy[lane]);
+            visit(PairKernel {
+                neighbor: self.neighbors[lane],
+                delta,
+                relative_velocity: Vector::new(self.vx[lane], self.vy[lane]),
+                distance_sq: lanes.distance_sq[lane],
+                distance: lanes.distance[lane],
+                poly6: lanes.poly6[lane],
+                gradient: delta * lanes.gradient_scale[lane],
+                laplacian: lanes.laplacian[lane],
+            });
+        }
+        self.len = 0;
+    }
+}
+
+impl KernelSet {
+    #[cfg(all(feature = "simd", not(feature = "f64")))]
+    pub(super) fn evaluate_lanes(&self, dx: &[Real; LANES], dy: &[Real; LANES]) -> KernelLanes {
+        use super::EPSILON;
+        use wide::{CmpGt, CmpLt, f32x8};
+
+        let zero = f32x8::ZERO;
+        let support = f32x8::splat(self.support_radius);
+        let support_sq = f32x8::splat(self.support_sq);
+        let dx = f32x8::from(*dx);
+        let dy = f32x8::from(*dy);
+
+        let distance_sq = dx * dx + dy * dy;
+        let distance = distance_sq.sqrt();
+        let poly6_delta = support_sq - distance_sq;
+        let remaining = support - distance;
+        let inside_sq = distance_sq.cmp_lt(support_sq);
+        let inside = distance.cmp_lt(support);
+        let separated = distance.cmp_gt(f32x8::splat(EPSILON));
+
+        let poly6 = inside_sq.blend(
+            f32x8::splat(self.poly6_coeff) * poly6_delta * poly6_delta * poly6_delta,
+            zero,
+        );
+        let gradient_scale = (inside & separated).blend(
+            f32x8::splat(self.spiky_grad_coeff) * remaining * remaining / distance,
+            zero,
+        );
+        let laplacian = inside.blend(
+            f32x8::splat(self.viscosity_laplacian_coeff) * remaining,
+            zero,
+        );
+
+        KernelLanes {
+            distance_sq: distance_sq.to_array(),
+            distance: distance.to_array(),
+            poly6: poly6.to_array(),
+            gradient_scale: gradient_scale.to_array(),
+            laplacian: laplacian.to_array(),
+        }
+    }
+
+    #[cfg(not(all(feature = "simd", not(feature = "f64"))))]
+    pub(super) fn evaluate_lanes(&self, dx: &[Real; LANES], dy: &[Real; LANES]) -> KernelLanes {
+        let mut lanes = KernelLanes::default();
+
+        for lane in 0..LANES {
+            let distance_sq = dx[lane] * dx[lane] + dy[lane] * dy[lane];
+            let distance = distance_sq.sqrt();
+            lanes.distance_sq[lane] = distance_sq;
+            lanes.distance[lane] = distance;
+            lanes.poly6[lane] = self.poly6(distance_sq);
+            lanes.gradient_scale[lane] = self.spiky_gradient_scale(distance);
+            lanes.laplacian[lane] = self.viscosity_laplacian(distance);
+        }
+
+        lanes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batched_kernels_match_scalar_kernels() {
+        let kernels = KernelSet::new(0.1155);
+        let deltas = (0..LANES * 5)
+            .map(|index| {
+                let angle = index as Real * 0.7;
+                let radius = index as Real * 0.003;
+                Vector::new(radius * angle.cos(), radius * angle.sin())
+            })
+            .collect::<Vec<_>>();
+
+        let mut batch = NeighborBatch::new();
+        let mut visited = Vec::new();
+        for (index, delta) in deltas.iter().enumerate() {
+            if batch.push(index, *delta, -*delta) {
+                batch.flush(&kernels, |pair| visited.push(pair));
+            }
+        }
+        batch.flush(&kernels, |pair| visited.push(pair));
+
+        assert_eq!(visited.len(), deltas.len());
+        for pair in visited {
+            let delta = deltas[pair.neighbor];
+            let distance_sq = delta.length_squared();
+            let gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+            let laplacian = kernels.viscosity_laplacian(distance_sq.sqrt());
+            let poly6 = kernels.poly6(distance_sq);
+
+            assert_eq!(pair.relative_velocity, -delta);
+            assert!((pair.poly6 - poly6).abs() <= 1.0e-5 * poly6.abs().max(1.0));
+            assert!((pair.gradient - gradient).length() <= 1.0e-4 * gradient.length().max(1.0));
+            assert!((pair.laplacian - laplacian).abs() <= 1.0e-4 * laplacian.abs().max(1.0));
+        }
+    }
+}
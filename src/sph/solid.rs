@@ -0,0 +1,283 @@
+use super::correction::correction_from_moments;
+use super::linalg::{Matrix2, SymmetricMatrix2};
+use super::{
+    EPSILON, MIN_PAR_CHUNK, Real, SolidMaterial, SolidModel, SphSimulation, Vector, clamp_magnitude,
+};
+use nannou::prelude::*;
+use rayon::prelude::*;
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct SolidState {
+    particles: Vec<usize>,
+    reference: Vec<Vector>,
+    neighbor_offsets: Vec<usize>,
+    neighbors: Vec<usize>,
+    kernel_gradients: Vec<Vector>,
+    kernel_weights: Vec<Real>,
+    corrections: Vec<Matrix2>,
+    deformations: Vec<Matrix2>,
+    stresses: Vec<Matrix2>,
+    plastic_strains: Vec<SymmetricMatrix2>,
+}
+
+impl SolidState {
+    pub(super) fn remap_particles(&mut self, new_slots: &[usize]) {
+        for particle in &mut self.particles {
+            *particle = new_slots[*particle];
+        }
+    }
+}
+
+impl SphSimulation {
+    pub fn pin_particles(&mut self, region: Rect<Real>) {
+        for (pinned, position) in self.pinned.iter_mut().zip(&self.positions) {
+            if region.contains_point(position.to_array()) {
+                *pinned = true;
+            }
+        }
+    }
+
+    pub fn deformation_gradients(&self) -> impl Iterator<Item = (u32, [Real; 4])> + '_ {
+        self.solids
+            .particles
+            .iter()
+            .zip(&self.solids.deformations)
+            .map(|(index, f)| (self.particle_ids[*index], [f.xx, f.xy, f.yx, f.yy]))
+    }
+
+    pub(super) fn attach_solid_body(&mut self, first: usize, count: usize) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let volume = config.particle_mass / config.rest_density;
+        let solids = &mut self.solids;
+        let base = solids.particles.len();
+        let body = &self.positions[first..first + count];
+
+        if solids.neighbor_offsets.is_empty() {
+            solids.neighbor_offsets.push(0);
+        }
+
+        for (offset, reference_i) in body.iter().copied().enumerate() {
+            let mut moments = Matrix2::ZERO;
+
+            for (neighbor, reference_j) in body.iter().copied().enumerate() {
+                let delta = reference_i - reference_j;
+                let distance_sq = delta.length_squared();
+
+                if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                    continue;
+                }
+
+                let gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+                moments = moments + Matrix2::outer(volume * gradient, -delta);
+                solids.neighbors.push(base + neighbor);
+                solids.kernel_gradients.push(gradient);
+                solids.kernel_weights.push(kernels.poly6(distance_sq));
+            }
+
+            solids.particles.push(first + offset);
+            solids.reference.push(reference_i);
+            solids.neighbor_offsets.push(solids.neighbors.len());
+            solids.corrections.push(correction_from_moments(
+                moments,
+                config.kernel_correction_min_determinant,
+            ));
+            solids.deformations.push(Matrix2::IDENTITY);
+            solids.stresses.push(Matrix2::ZERO);
+            solids.plastic_strains.push(SymmetricMatrix2::ZERO);
+        }
+    }
+
+    pub(super) fn apply_solid_forces(&mut self) {
+        if self.solids.particles.is_empty() {
+            return;
+        }
+
+        let config = self.config;
+        let material = config.solid;
+        let volume = config.particle_mass / config.rest_density;
+        let positions = &self.positions;
+        let solids = &mut self.solids;
+        let particles = &solids.particles;
+        let neighbor_offsets = &solids.neighbor_offsets;
+        let neighbors = &solids.neighbors;
+        let kernel_gradients = &solids.kernel_gradients;
+        let corrections = &solids.corrections;
+
+        solids
+            .deformations
+            .par_iter_mut()
+            .zip(solids.stresses.par_iter_mut())
+            .zip(solids.plastic_strains.par_iter_mut())
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(local, ((deformation, stress), plastic_strain))| {
+                let position_a = positions[particles[local]];
+                let mut gradient = Matrix2::ZERO;
+
+                for pair in neighbor_offsets[local]..neighbor_offsets[local + 1] {
+                    let position_b = positions[particles[neighbors[pair]]];
+                    let corrected = corrections[local].transform(kernel_gradients[pair]);
+                    gradient =
+                        gradient + Matrix2::outer(position_b - position_a, volume * corrected);
+                }
+
+                *deformation = gradient;
+                *stress = first_piola_stress(material, gradient, plastic_strain);
+            });
+
+        let solids = &self.solids;
+        let accelerations = (0..solids.particles.len())
+            .into_par_iter()
+            .with_min_len(MIN_PAR_CHUNK)
+            .map(|local| {
+                let position_a = positions[solids.particles[local]];
+                let mut force = Vector::ZERO;
+                let mut hourglass = Vector::ZERO;
+
+                for pair in solids.neighbor_offsets[local]..solids.neighbor_offsets[local + 1] {
+                    let neighbor = solids.neighbors[pair];
+                    let gradient = solids.kernel_gradients[pair];
+                    let traction = solids.stresses[local]
+                        .transform(solids.corrections[local].transform(gradient))
+                        + solids.stresses[neighbor]
+                            .transform(solids.corrections[neighbor].transform(gradient));
+                    force += volume * volume * traction;
+
+                    let current = positions[solids.particles[neighbor]] - position_a;
+                    let reference = solids.reference[neighbor] - solids.reference[local];
+                    let distance = current.length();
+                    if distance <= EPSILON {
+                        continue;
+                    }
+
+                    let predicted = (solids.deformations[local] + solids.deformations[neighbor])
+                        .transform(reference);
+                    let error = (predicted - 2.0 * current).dot(current / distance);
+                    hourglass -= 0.5
+                        * material.hourglass_control
+                        * material.youngs_modulus
+                        * volume
+                        * volume
+                        * solids.kernel_weights[pair]
+                        * error
+                        / reference.length_squared()
+                        * current
+                        / distance;
+                }
+
+                (force + hourglass) / config.particle_mass
+            })
+            .collect::<Vec<_>>();
+
+        for (index, acceleration) in solids.particles.iter().zip(accelerations) {
+            self.accelerations[*index] = clamp_magnitude(
+                self.accelerations[*index] + acceleration,
+                config.max_acceleration,
+            );
+        }
+    }
+}
+
+fn first_piola_stress(
+    material: SolidMaterial,
+    deformation: Matrix2,
+    plastic_strain: &mut SymmetricMatrix2,
+) -> Matrix2 {
+    let (lambda, mu) = material.lame_parameters();
+    let plastic_inverse = (Matrix2::IDENTITY + Matrix2::from(*plastic_strain))
+        .inverse()
+        .unwrap_or(Matrix2::IDENTITY);
+    let mut elastic = deformation * plastic_inverse;
+
+    if let Some(yield_stress) = material.yield_stress {
+        let strain = Matrix2::from(elastic.symmetric_part()) - Matrix2::IDENTITY;
+        let deviatoric = strain - Matrix2::IDENTITY.scaled(0.5 * strain.trace());
+        let von_mises = 2.0 * mu * Real::sqrt(1.5) * deviatoric.symmetric_part().norm();
+
+        if von_mises > yield_stress {
+            let increment = deviatoric
+                .symmetric_part()
+                .scaled(1.0 - yield_stress / von_mises);
+            *plastic_strain = *plastic_strain + increment;
+            let plastic_inverse = (Matrix2::IDENTITY + Matrix2::from(*plastic_strain))
+                .inverse()
+                .unwrap_or(Matrix2::IDENTITY);
+            elastic = deformation * plastic_inverse;
+        }
+    }
+
+    match material.model {
+        SolidModel::LinearElastic => {
+            let strain = Matrix2::from(elastic.symmetric_part()) - Matrix2::IDENTITY;
+            Matrix2::IDENTITY.scaled(lambda * strain.trace()) + strain.scaled(2.0 * mu)
+        }
+        SolidModel::NeoHookean => {
+            let jacobian = elastic.determinant();
+            let Some(inverse) = elastic.inverse().filter(|_| jacobian > EPSILON) else {
+                return Matrix2::ZERO;
+            };
+            let inverse_transpose = inverse.transpose();
+
+            (elastic - inverse_transpose).scaled(mu)
+                + inverse_transpose.scaled(lambda * jacobian.ln())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Phase, SphConfig};
+    use super::*;
+
+    fn stretched_bar(model: SolidModel) -> SphSimulation {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.solid.model = model;
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block_with_phase(20, 6, Vector::new(-0.5, 0.0), Phase::Solid);
+        for position in &mut simulation.positions {
+            position.x *= 1.01;
+        }
+        simulation.accelerations.fill(Vector::ZERO);
+        simulation.apply_solid_forces();
+        simulation
+    }
+
+    #[test]
+    fn stretched_bar_recovers_deformation_and_pulls_back() {
+        for model in [SolidModel::LinearElastic, SolidModel::NeoHookean] {
+            let simulation = stretched_bar(model);
+            let (_, center) = simulation
+                .deformation_gradients()
+                .find(|(index, _)| *index == 3 * 20 + 10)
+                .unwrap();
+
+            assert!((center[0] - 1.01).abs() < 1.0e-3);
+            assert!(center[1].abs() < 1.0e-3 && (center[3] - 1.0).abs() < 1.0e-3);
+            assert!(simulation.accelerations[3 * 20].x > 0.0);
+            assert!(simulation.accelerations[3 * 20 + 19].x < 0.0);
+            assert!(simulation.accelerations[3 * 20 + 10].x.abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn plasticity_keeps_a_permanent_set() {
+        let mut material = SolidMaterial {
+            model: SolidModel::LinearElastic,
+            yield_stress: Some(50.0),
+            ..SolidMaterial::default()
+        };
+        let mut plastic_strain = SymmetricMatrix2::ZERO;
+        let stretch = Matrix2 {
+            xx: 1.05,
+            ..Matrix2::IDENTITY
+        };
+
+        first_piola_stress(material, stretch, &mut plastic_strain);
+        assert!(plastic_strain.xx > 0.0);
+
+        material.yield_stress = None;
+        let unloaded = first_piola_stress(material, Matrix2::IDENTITY, &mut plastic_strain);
+        assert!(unloaded.xx < 0.0);
+    }
+}
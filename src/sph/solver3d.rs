@@ -0,0 +1,701 @@
+use super::diagnostics::relative_drift;
+use super::real::consts::PI;
+use super::sort::CountingSort;
+use super::{EPSILON, MIN_PAR_CHUNK, Real, Vector3, clamp_magnitude, determinism, export};
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds3 {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Bounds3 {
+    pub fn from_size(size: Vector3) -> Self {
+        Self {
+            min: -0.5 * size,
+            max: 0.5 * size,
+        }
+    }
+
+    pub fn size(&self) -> Vector3 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vector3 {
+        0.5 * (self.min + self.max)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sph3dConfig {
+    pub bounds: Bounds3,
+    pub particle_spacing: Real,
+    pub particle_radius: Real,
+    pub particle_mass: Real,
+    pub smoothing_radius: Real,
+    pub rest_density: Real,
+    pub pressure_stiffness: Real,
+    pub gamma: Real,
+    pub parallel_grid_rebuild: bool,
+    pub deterministic: bool,
+    pub gravity: Vector3,
+    pub viscosity: Real,
+    pub xsph_factor: Real,
+    pub boundary_stiffness: Real,
+    pub boundary_damping: Real,
+    pub boundary_restitution: Real,
+    pub boundary_margin: Real,
+    pub max_density_ratio: Real,
+    pub max_acceleration: Real,
+    pub max_velocity: Real,
+    pub velocity_damping: Real,
+}
+
+impl Sph3dConfig {
+    pub fn new(bounds: Bounds3) -> Self {
+        let particle_spacing = 0.055;
+        let smoothing_radius = particle_spacing * 2.1;
+        let rest_density = 1000.0;
+        let gamma = 7.0;
+        let sound_speed = 8.5;
+
+        Self {
+            bounds,
+            particle_spacing,
+            particle_radius: particle_spacing * 0.42,
+            particle_mass: rest_density * particle_spacing.powi(3),
+            smoothing_radius,
+            rest_density,
+            pressure_stiffness: rest_density * sound_speed * sound_speed / gamma,
+            gamma,
+            parallel_grid_rebuild: true,
+            deterministic: false,
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            viscosity: 0.14,
+            xsph_factor: 0.04,
+            boundary_stiffness: 240.0,
+            boundary_damping: 18.0,
+            boundary_restitution: 0.15,
+            boundary_margin: particle_spacing * 0.5,
+            max_density_ratio: 2.4,
+            max_acceleration: 320.0,
+            max_velocity: 14.0,
+            velocity_damping: 0.9992,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sph3dStats {
+    pub particle_count: usize,
+    pub threads: usize,
+    pub max_speed: Real,
+    pub max_density_ratio: Real,
+    pub kinetic_energy: Real,
+    pub potential_energy: Real,
+    pub elastic_energy: Real,
+    pub volume_drift: Real,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct KernelSet3 {
+    support_radius: Real,
+    support_sq: Real,
+    poly6_coeff: Real,
+    spiky_grad_coeff: Real,
+    viscosity_laplacian_coeff: Real,
+}
+
+impl KernelSet3 {
+    fn new(smoothing_radius: Real) -> Self {
+        Self {
+            support_radius: smoothing_radius,
+            support_sq: smoothing_radius * smoothing_radius,
+            poly6_coeff: 315.0 / (64.0 * PI * smoothing_radius.powi(9)),
+            spiky_grad_coeff: -45.0 / (PI * smoothing_radius.powi(6)),
+            viscosity_laplacian_coeff: 45.0 / (PI * smoothing_radius.powi(6)),
+        }
+    }
+
+    #[inline]
+    fn poly6(&self, distance_sq: Real) -> Real {
+        if distance_sq >= self.support_sq {
+            return 0.0;
+        }
+
+        let delta = self.support_sq - distance_sq;
+        self.poly6_coeff * delta * delta * delta
+    }
+
+    #[inline]
+    fn spiky_gradient(&self, delta: Vector3, distance: Real) -> Vector3 {
+        if distance <= EPSILON || distance >= self.support_radius {
+            return Vector3::ZERO;
+        }
+
+        delta * (self.spiky_grad_coeff * (self.support_radius - distance).powi(2) / distance)
+    }
+
+    #[inline]
+    fn viscosity_laplacian(&self, distance: Real) -> Real {
+        if distance >= self.support_radius {
+            return 0.0;
+        }
+
+        self.viscosity_laplacian_coeff * (self.support_radius - distance)
+    }
+}
+
+#[derive(Debug)]
+struct UniformGrid3 {
+    sorter: CountingSort,
+    cell_offsets: Vec<usize>,
+    sorted_particles: Vec<usize>,
+    particle_cells: Vec<usize>,
+    dims: [usize; 3],
+    inv_cell_size: Real,
+    origin: Vector3,
+}
+
+impl UniformGrid3 {
+    fn new(bounds: Bounds3, cell_size: Real, parallel: bool) -> Self {
+        let size = bounds.size();
+        let dims = [size.x, size.y, size.z]
+            .map(|extent| (extent / cell_size).ceil().max(1.0) as usize + 1);
+
+        Self {
+            sorter: CountingSort::new(parallel),
+            cell_offsets: vec![0; dims[0] * dims[1] * dims[2] + 1],
+            sorted_particles: Vec::new(),
+            particle_cells: Vec::new(),
+            dims,
+            inv_cell_size: cell_size.recip(),
+            origin: bounds.min,
+        }
+    }
+
+    fn rebuild(&mut self, positions: &[Vector3]) {
+        if self.sorted_particles.len() != positions.len() {
+            self.sorted_particles.resize(positions.len(), 0);
+            self.particle_cells.resize(positions.len(), 0);
+        }
+
+        let mut particle_cells = std::mem::take(&mut self.particle_cells);
+        particle_cells
+            .par_iter_mut()
+            .zip(positions.par_iter())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(cell, position)| {
+                let [col, row, layer] = self.cell_coords(*position);
+                *cell = (layer * self.dims[1] + row) * self.dims[0] + col;
+            });
+        self.particle_cells = particle_cells;
+
+        self.sorter.sort(
+            &self.particle_cells,
+            &mut self.cell_offsets,
+            &mut self.sorted_particles,
+        );
+    }
+
+    #[inline]
+    fn cell_coords(&self, position: Vector3) -> [usize; 3] {
+        let coords = ((position - self.origin) * self.inv_cell_size).floor();
+        let clamp = |coord: Real, dim: usize| (coord.max(0.0) as usize).min(dim - 1);
+
+        [
+            clamp(coords.x, self.dims[0]),
+            clamp(coords.y, self.dims[1]),
+            clamp(coords.z, self.dims[2]),
+        ]
+    }
+
+    #[inline]
+    fn for_each_near<F>(&self, position: Vector3, mut visit: F)
+    where
+        F: FnMut(usize),
+    {
+        let [col, row, layer] = self.cell_coords(position);
+        let [cols, rows, layers] = self.dims;
+
+        for current_layer in layer.saturating_sub(1)..=(layer + 1).min(layers - 1) {
+            for current_row in row.saturating_sub(1)..=(row + 1).min(rows - 1) {
+                let first = (current_layer * rows + current_row) * cols;
+                let start = self.cell_offsets[first + col.saturating_sub(1)];
+                let end = self.cell_offsets[first + (col + 1).min(cols - 1) + 1];
+
+                for slot in start..end {
+                    visit(self.sorted_particles[slot]);
+                }
+            }
+        }
+    }
+}
+
+pub struct Sph3dSimulation {
+    config: Sph3dConfig,
+    kernels: KernelSet3,
+    positions: Vec<Vector3>,
+    velocities: Vec<Vector3>,
+    densities: Vec<Real>,
+    pressures: Vec<Real>,
+    pressure_terms: Vec<Real>,
+    inv_densities: Vec<Real>,
+    accelerations: Vec<Vector3>,
+    xsph_corrections: Vec<Vector3>,
+    grid: UniformGrid3,
+    reference_volume: Real,
+    step_count: u64,
+}
+
+impl Sph3dSimulation {
+    pub fn new(config: Sph3dConfig) -> Self {
+        Self {
+            config,
+            kernels: KernelSet3::new(config.smoothing_radius),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            densities: Vec::new(),
+            pressures: Vec::new(),
+            pressure_terms: Vec::new(),
+            inv_densities: Vec::new(),
+            accelerations: Vec::new(),
+            xsph_corrections: Vec::new(),
+            grid: UniformGrid3::new(
+                config.bounds,
+                config.smoothing_radius,
+                config.parallel_grid_rebuild,
+            ),
+            reference_volume: 0.0,
+            step_count: 0,
+        }
+    }
+
+    pub fn seed_block(&mut self, cols: usize, rows: usize, layers: usize, origin: Vector3) {
+        let additional = cols * rows * layers;
+        self.positions.reserve(additional);
+        self.velocities.reserve(additional);
+        self.densities.reserve(additional);
+        self.pressures.reserve(additional);
+        self.pressure_terms.reserve(additional);
+        self.inv_densities.reserve(additional);
+        self.accelerations.reserve(additional);
+        self.xsph_corrections.reserve(additional);
+
+        let spacing = self.config.particle_spacing;
+        for layer in 0..layers {
+            for row in 0..rows {
+                for col in 0..cols {
+                    self.positions.push(
+                        origin + spacing * Vector3::new(col as Real, row as Real, layer as Real),
+                    );
+                    self.velocities.push(Vector3::ZERO);
+                    self.densities.push(self.config.rest_density);
+                    self.pressures.push(0.0);
+                    self.pressure_terms.push(0.0);
+                    self.inv_densities.push(self.config.rest_density.recip());
+                    self.accelerations.push(Vector3::ZERO);
+                    self.xsph_corrections.push(Vector3::ZERO);
+                }
+            }
+        }
+
+        self.reference_volume +=
+            additional as Real * self.config.particle_mass / self.config.rest_density;
+    }
+
+    pub fn config(&self) -> &Sph3dConfig {
+        &self.config
+    }
+
+    pub fn positions(&self) -> &[Vector3] {
+        &self.positions
+    }
+
+    pub fn velocities(&self) -> &[Vector3] {
+        &self.velocities
+    }
+
+    pub fn densities(&self) -> &[Real] {
+        &self.densities
+    }
+
+    pub fn pressures(&self) -> &[Real] {
+        &self.pressures
+    }
+
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    pub fn step(&mut self, dt: Real) {
+        if self.positions.is_empty() {
+            return;
+        }
+
+        self.rebuild_neighbors();
+        self.compute_densities();
+        self.compute_pressures();
+        self.compute_accelerations();
+        self.integrate(dt);
+        self.step_count += 1;
+    }
+
+    #[doc(hidden)]
+    pub fn rebuild_neighbors(&mut self) {
+        self.grid.rebuild(&self.positions);
+    }
+
+    #[doc(hidden)]
+    pub fn compute_densities(&mut self) {
+        let positions = &self.positions;
+        let grid = &self.grid;
+        let kernels = self.kernels;
+        let particle_mass = self.config.particle_mass;
+        let minimum_density = self.config.rest_density * 0.5;
+
+        self.densities
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, density)| {
+                let position_i = positions[index];
+                let mut value = 0.0;
+
+                grid.for_each_near(position_i, |neighbor| {
+                    let distance_sq = (position_i - positions[neighbor]).length_squared();
+                    value += particle_mass * kernels.poly6(distance_sq);
+                });
+
+                *density = value.max(minimum_density);
+            });
+    }
+
+    #[doc(hidden)]
+    pub fn compute_pressures(&mut self) {
+        let config = self.config;
+
+        self.pressures
+            .par_iter_mut()
+            .zip(self.inv_densities.par_iter_mut())
+            .zip(self.pressure_terms.par_iter_mut())
+            .zip(self.densities.par_iter().copied())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(((pressure, inv_density), pressure_term), density)| {
+                let density_inv = density.max(EPSILON).recip();
+                let ratio = (density / config.rest_density).clamp(1.0, config.max_density_ratio);
+                let value = config.pressure_stiffness * (ratio.powf(config.gamma) - 1.0);
+
+                *pressure = value;
+                *inv_density = density_inv;
+                *pressure_term = value * density_inv * density_inv;
+            });
+    }
+
+    #[doc(hidden)]
+    pub fn compute_accelerations(&mut self) {
+        let config = self.config;
+        let kernels = self.kernels;
+        let positions = &self.positions;
+        let velocities = &self.velocities;
+        let inv_densities = &self.inv_densities;
+        let pressure_terms = &self.pressure_terms;
+        let grid = &self.grid;
+
+        self.accelerations
+            .par_iter_mut()
+            .zip(self.xsph_corrections.par_iter_mut())
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, (acceleration, xsph))| {
+                let position_i = positions[index];
+                let velocity_i = velocities[index];
+                let pressure_term_i = pressure_terms[index];
+
+                let mut pressure_force = Vector3::ZERO;
+                let mut viscosity_force = Vector3::ZERO;
+                let mut velocity_blend = Vector3::ZERO;
+
+                grid.for_each_near(position_i, |neighbor| {
+                    if neighbor == index {
+                        return;
+                    }
+
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+                    if distance_sq >= kernels.support_sq {
+                        return;
+                    }
+
+                    let distance = distance_sq.sqrt();
+                    let mass_density_j = config.particle_mass * inv_densities[neighbor];
+                    let velocity_delta = velocities[neighbor] - velocity_i;
+
+                    pressure_force -= config.particle_mass
+                        * (pressure_term_i + pressure_terms[neighbor])
+                        * kernels.spiky_gradient(delta, distance);
+                    viscosity_force += config.viscosity
+                        * mass_density_j
+                        * velocity_delta
+                        * kernels.viscosity_laplacian(distance);
+                    velocity_blend += mass_density_j * velocity_delta * kernels.poly6(distance_sq);
+                });
+
+                let total = pressure_force
+                    + viscosity_force
+                    + config.gravity
+                    + Self::boundary_acceleration(config, position_i, velocity_i);
+                *acceleration = clamp_magnitude(total, config.max_acceleration);
+                *xsph = config.xsph_factor * velocity_blend;
+            });
+    }
+
+    #[doc(hidden)]
+    pub fn integrate(&mut self, dt: Real) {
+        let config = self.config;
+
+        self.positions
+            .par_iter_mut()
+            .zip(self.velocities.par_iter_mut())
+            .zip(self.accelerations.par_iter().copied())
+            .zip(self.xsph_corrections.par_iter().copied())
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(((position, velocity), acceleration), xsph)| {
+                *velocity += acceleration * dt;
+                *velocity =
+                    clamp_magnitude(*velocity, config.max_velocity) * config.velocity_damping;
+                *position += (*velocity + xsph) * dt;
+
+                if !position.is_finite() {
+                    *position = config.bounds.center();
+                }
+                if !velocity.is_finite() {
+                    *velocity = Vector3::ZERO;
+                }
+
+                Self::resolve_boundaries(config, position, velocity);
+            });
+    }
+
+    pub fn stats(&self) -> Sph3dStats {
+        let config = &self.config;
+        let mass = config.particle_mass;
+        let equation_of_state = config.equation_of_state();
+        let (kinetic_energy, potential_energy, elastic_energy, volume) = determinism::reduce(
+            self.positions
+                .par_iter()
+                .zip(self.velocities.par_iter())
+                .zip(self.densities.par_iter())
+                .with_min_len(MIN_PAR_CHUNK)
+                .map(|((position, velocity), density)| {
+                    (
+                        0.5 * mass * velocity.length_squared(),
+                        -mass * config.gravity.dot(*position - config.bounds.min),
+                        mass * equation_of_state.elastic_energy_per_mass(*density),
+                        mass / density.max(Real::EPSILON),
+                    )
+                }),
+            config.deterministic,
+            || (0.0, 0.0, 0.0, 0.0),
+            |total, particle| {
+                (
+                    total.0 + particle.0,
+                    total.1 + particle.1,
+                    total.2 + particle.2,
+                    total.3 + particle.3,
+                )
+            },
+        );
+
+        Sph3dStats {
+            particle_count: self.positions.len(),
+            threads: rayon::current_num_threads(),
+            max_speed: self
+                .velocities
+                .par_iter()
+                .with_min_len(MIN_PAR_CHUNK)
+                .map(|velocity| velocity.length())
+                .reduce(|| 0.0, Real::max),
+            max_density_ratio: self
+                .densities
+                .par_iter()
+                .with_min_len(MIN_PAR_CHUNK)
+                .map(|density| density / config.rest_density)
+                .reduce(|| 0.0, Real::max),
+            kinetic_energy,
+            potential_energy,
+            elastic_energy,
+            volume_drift: relative_drift(volume, self.reference_volume),
+        }
+    }
+
+    pub fn write_particles_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+
+        export::write_particle_rows(
+            &mut writer,
+            &[],
+            (0..self.positions.len()).map(|index| export::ParticleRow {
+                index,
+                position: self.positions[index].to_array(),
+                velocity: self.velocities[index].to_array(),
+                density: self.densities[index],
+                pressure: self.pressures[index],
+            }),
+            |_, _| Ok(()),
+        )?;
+
+        writer.flush()
+    }
+
+    pub fn write_particles_vtp(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+
+        export::write_particle_points(
+            &mut writer,
+            &(0..self.positions.len()).collect::<Vec<_>>(),
+            |index| self.positions[index].to_array(),
+            |index| self.velocities[index].to_array(),
+            &[("density", &self.densities), ("pressure", &self.pressures)],
+        )?;
+
+        writer.flush()
+    }
+
+    fn boundary_acceleration(config: Sph3dConfig, position: Vector3, velocity: Vector3) -> Vector3 {
+        let walls = config.wall_response();
+        let mut acceleration = Vector3::ZERO;
+
+        for axis in 0..3 {
+            let (repulsion, damping) = walls.acceleration(
+                position[axis] - config.bounds.min[axis],
+                config.bounds.max[axis] - position[axis],
+                velocity[axis],
+            );
+            acceleration[axis] = repulsion + damping;
+        }
+
+        acceleration
+    }
+
+    fn resolve_boundaries(config: Sph3dConfig, position: &mut Vector3, velocity: &mut Vector3) {
+        let walls = config.wall_response();
+        let min = config.bounds.min + Vector3::splat(config.boundary_margin);
+        let max = config.bounds.max - Vector3::splat(config.boundary_margin);
+
+        for axis in 0..3 {
+            walls.resolve(
+                &mut position[axis],
+                &mut velocity[axis],
+                min[axis],
+                max[axis],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded(config: Sph3dConfig) -> Sph3dSimulation {
+        let mut simulation = Sph3dSimulation::new(config);
+        simulation.seed_block(8, 8, 6, Vector3::new(-0.3, -0.3, -0.2));
+        simulation
+    }
+
+    fn default_config() -> Sph3dConfig {
+        Sph3dConfig::new(Bounds3::from_size(Vector3::new(1.0, 1.0, 0.6)))
+    }
+
+    #[test]
+    fn poly6_kernel_normalizes_in_three_dimensions() {
+        let kernels = KernelSet3::new(0.1155);
+        let samples = 48;
+        let step = 2.0 * kernels.support_radius / samples as Real;
+        let mut integral = 0.0;
+        for x in 0..samples {
+            for y in 0..samples {
+                for z in 0..samples {
+                    let offset =
+                        Vector3::new(x as Real, y as Real, z as Real) + Vector3::splat(0.5);
+                    let point = offset * step - Vector3::splat(kernels.support_radius);
+                    integral += kernels.poly6(point.length_squared()) * step.powi(3);
+                }
+            }
+        }
+
+        assert!((integral - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn grid_finds_every_neighbor_in_three_dimensions() {
+        let config = default_config();
+        let mut simulation = seeded(config);
+        simulation.positions[5] += Vector3::new(0.012, -0.021, 0.017);
+        simulation.rebuild_neighbors();
+
+        let support_sq = config.smoothing_radius * config.smoothing_radius;
+        for (index, position) in simulation.positions.iter().enumerate() {
+            let mut found = Vec::new();
+            simulation.grid.for_each_near(*position, |neighbor| {
+                if (*position - simulation.positions[neighbor]).length_squared() < support_sq {
+                    found.push(neighbor);
+                }
+            });
+            found.sort_unstable();
+
+            let expected = (0..simulation.positions.len())
+                .filter(|other| {
+                    (*position - simulation.positions[*other]).length_squared() < support_sq
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected, "particle {index}");
+        }
+    }
+
+    #[test]
+    fn stepping_keeps_particles_inside_the_box() {
+        let config = default_config();
+        let mut simulation = seeded(config);
+
+        for _ in 0..60 {
+            simulation.step(1.0 / 240.0);
+        }
+
+        assert_eq!(simulation.step_count(), 60);
+        assert!(simulation.stats().max_speed.is_finite());
+        assert!(simulation.positions.iter().all(|position| {
+            position.cmpge(config.bounds.min).all() && position.cmple(config.bounds.max).all()
+        }));
+    }
+
+    #[test]
+    fn stats_report_eos_energy_and_volume_drift_under_compression() {
+        let config = default_config();
+        let mut simulation = seeded(config);
+        let at_rest = simulation.stats();
+
+        assert_eq!(at_rest.elastic_energy, 0.0);
+        assert!(at_rest.volume_drift.abs() < 1e-5);
+
+        let center = simulation
+            .positions
+            .iter()
+            .fold(Vector3::ZERO, |sum, position| sum + *position)
+            / simulation.positions.len() as Real;
+        for position in &mut simulation.positions {
+            *position = center + (*position - center) * 0.8;
+        }
+        simulation.rebuild_neighbors();
+        simulation.compute_densities();
+        let compressed = simulation.stats();
+
+        assert!(compressed.max_density_ratio > 1.0);
+        assert!(compressed.elastic_energy > 0.0);
+        assert!(compressed.volume_drift < -0.05);
+    }
+}
@@ -0,0 +1,207 @@
+use rayon::prelude::*;
+
+const MAX_SORT_CHUNKS: usize = 32;
+const CHUNKS_PER_THREAD: usize = 2;
+const MAX_COUNTS_PER_KEY: usize = 4;
+const MIN_SORT_CHUNK: usize = 4096;
+const SCAN_BLOCK: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub(super) struct CountingSort {
+    counts: Vec<usize>,
+    cursors: Vec<usize>,
+    parallel: bool,
+}
+
+impl CountingSort {
+    pub(super) fn new(parallel: bool) -> Self {
+        Self {
+            counts: Vec::new(),
+            cursors: Vec::new(),
+            parallel,
+        }
+    }
+
+    pub(super) fn sort(&mut self, keys: &[usize], offsets: &mut [usize], sorted: &mut [usize]) {
+        let key_count = offsets.len() - 1;
+        let threads = rayon::current_num_threads();
+        let chunk_count = (threads * CHUNKS_PER_THREAD).min(MAX_SORT_CHUNKS);
+        let chunk_size = keys
+            .len()
+            .div_ceil(chunk_count)
+            .max(MIN_SORT_CHUNK)
+            .max(key_count / MAX_COUNTS_PER_KEY);
+
+        if self.parallel && threads > 1 && keys.len() > chunk_size {
+            self.sort_parallel(keys, offsets, sorted, chunk_size);
+        } else {
+            self.sort_serial(keys, offsets, sorted);
+        }
+    }
+
+    fn sort_serial(&mut self, keys: &[usize], offsets: &mut [usize], sorted: &mut [usize]) {
+        let key_count = offsets.len() - 1;
+        self.cursors.clear();
+        self.cursors.resize(key_count, 0);
+
+        for &key in keys {
+            self.cursors[key] += 1;
+        }
+
+        let mut offset = 0;
+        for (key, cursor) in self.cursors.iter_mut().enumerate() {
+            let count = *cursor;
+            offsets[key] = offset;
+            *cursor = offset;
+            offset += count;
+        }
+        offsets[key_count] = offset;
+
+        for (index, &key) in keys.iter().enumerate() {
+            let slot = self.cursors[key];
+            sorted[slot] = index;
+            self.cursors[key] = slot + 1;
+        }
+    }
+
+    fn sort_parallel(
+        &mut self,
+        keys: &[usize],
+        offsets: &mut [usize],
+        sorted: &mut [usize],
+        chunk_size: usize,
+    ) {
+        let key_count = offsets.len() - 1;
+        let chunks = keys.len().div_ceil(chunk_size);
+        self.counts.resize(chunks * key_count, 0);
+        self.cursors.resize(chunks * key_count, 0);
+
+        self.counts
+            .par_chunks_mut(key_count)
+            .zip(keys.par_chunks(chunk_size))
+            .for_each(|(counts, chunk_keys)| {
+                counts.fill(0);
+                for &key in chunk_keys {
+                    counts[key] += 1;
+                }
+            });
+
+        let counts = &self.counts;
+        offsets[..key_count]
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(SCAN_BLOCK)
+            .for_each(|(key, total)| {
+                *total = (0..chunks)
+                    .map(|chunk| counts[chunk * key_count + key])
+                    .sum();
+            });
+        offsets[key_count] = exclusive_scan(&mut offsets[..key_count]);
+
+        self.cursors[..key_count].copy_from_slice(&offsets[..key_count]);
+        for chunk in 1..chunks {
+            let (previous, current) = self.cursors.split_at_mut(chunk * key_count);
+            let previous = &previous[(chunk - 1) * key_count..];
+            let previous_counts = &counts[(chunk - 1) * key_count..chunk * key_count];
+
+            current[..key_count]
+                .par_iter_mut()
+                .zip(previous.par_iter().zip(previous_counts.par_iter()))
+                .with_min_len(SCAN_BLOCK)
+                .for_each(|(cursor, (start, count))| *cursor = start + count);
+        }
+
+        let slots = SlotWriter(sorted.as_mut_ptr());
+        self.cursors
+            .par_chunks_mut(key_count)
+            .zip(keys.par_chunks(chunk_size))
+            .enumerate()
+            .for_each(|(chunk, (cursors, chunk_keys))| {
+                let first = chunk * chunk_size;
+                for (offset, &key) in chunk_keys.iter().enumerate() {
+                    let slot = cursors[key];
+                    cursors[key] = slot + 1;
+                    // SAFETY: each (chunk, key) pair owns the disjoint slot range that starts at its
+                    // cursor and spans its count, and all ranges lie inside `sorted`.
+                    unsafe { slots.write(slot, first + offset) };
+                }
+            });
+    }
+}
+
+struct SlotWriter(*mut usize);
+
+unsafe impl Sync for SlotWriter {}
+
+impl SlotWriter {
+    #[inline]
+    unsafe fn write(&self, slot: usize, value: usize) {
+        unsafe { self.0.add(slot).write(value) };
+    }
+}
+
+pub(super) fn exclusive_scan(values: &mut [usize]) -> usize {
+    let block_totals = values
+        .par_chunks(SCAN_BLOCK)
+        .map(|block| block.iter().sum::<usize>())
+        .collect::<Vec<_>>();
+
+    let mut total = 0;
+    let block_starts = block_totals
+        .iter()
+        .map(|block_total| {
+            let start = total;
+            total += block_total;
+            start
+        })
+        .collect::<Vec<_>>();
+
+    values
+        .par_chunks_mut(SCAN_BLOCK)
+        .zip(block_starts.par_iter().copied())
+        .for_each(|(block, mut running)| {
+            for value in block {
+                let count = *value;
+                *value = running;
+                running += count;
+            }
+        });
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_sort_matches_serial_sort() {
+        let key_count = 997;
+        let mut state = 0x2545_f491_u64;
+        let keys = (0..200_000)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                (state >> 33) as usize % key_count
+            })
+            .collect::<Vec<_>>();
+
+        let mut serial_offsets = vec![0; key_count + 1];
+        let mut serial_sorted = vec![0; keys.len()];
+        CountingSort::new(false).sort(&keys, &mut serial_offsets, &mut serial_sorted);
+
+        let mut parallel_offsets = vec![0; key_count + 1];
+        let mut parallel_sorted = vec![0; keys.len()];
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap()
+            .install(|| {
+                CountingSort::new(true).sort(&keys, &mut parallel_offsets, &mut parallel_sorted)
+            });
+
+        assert_eq!(serial_offsets, parallel_offsets);
+        assert_eq!(serial_sorted, parallel_sorted);
+    }
+}
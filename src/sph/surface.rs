@@ -0,0 +1,357 @@
+use super::export;
+use super::{EPSILON, NeighborGrid, NeighborSearch, Real, SphSimulation, Vector};
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SurfaceField {
+    ColorField { iso_level: Real },
+    ZhuBridson { particle_radius: Real },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Polyline {
+    pub points: Vec<Vector>,
+    pub closed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct FreeSurface {
+    pub bounds: Rect<Real>,
+    pub polylines: Vec<Polyline>,
+}
+
+impl Polyline {
+    pub fn segments(&self) -> impl Iterator<Item = (Vector, Vector)> + '_ {
+        let closing = if self.closed && self.points.len() > 2 {
+            self.points
+                .last()
+                .copied()
+                .zip(self.points.first().copied())
+        } else {
+            None
+        };
+
+        self.points
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .chain(closing)
+    }
+}
+
+impl FreeSurface {
+    pub fn elevation(&self, x: Real) -> Option<Real> {
+        self.polylines
+            .iter()
+            .flat_map(Polyline::segments)
+            .filter_map(|(start, end)| {
+                let (low, high) = if start.x <= end.x {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+
+                if x < low.x || x > high.x || high.x - low.x <= EPSILON {
+                    return None;
+                }
+
+                Some(low.y + (high.y - low.y) * (x - low.x) / (high.x - low.x))
+            })
+            .reduce(Real::max)
+    }
+
+    pub fn elevations(&self, xs: &[Real]) -> Vec<Option<Real>> {
+        xs.iter().map(|x| self.elevation(*x)).collect()
+    }
+
+    pub fn write_svg(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+        let stroke = self.bounds.w().max(self.bounds.h()) * 2.0e-3;
+
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+            self.bounds.left(),
+            -self.bounds.top(),
+            self.bounds.w(),
+            self.bounds.h(),
+        )?;
+        for polyline in &self.polylines {
+            let element = if polyline.closed {
+                "polygon"
+            } else {
+                "polyline"
+            };
+            write!(writer, "<{element} points=\"")?;
+            for point in &polyline.points {
+                write!(writer, "{},{} ", point.x, -point.y)?;
+            }
+            writeln!(
+                writer,
+                "\" fill=\"none\" stroke=\"#3fa9f5\" stroke-width=\"{stroke}\"/>"
+            )?;
+        }
+        writeln!(writer, "</svg>")?;
+
+        writer.flush()
+    }
+
+    pub fn write_vtp(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = export::create(path.as_ref())?;
+        let point_count = self
+            .polylines
+            .iter()
+            .map(|polyline| polyline.points.len())
+            .sum::<usize>();
+
+        writeln!(writer, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            writer,
+            "<VTKFile type=\"PolyData\" version=\"0.1\" byte_order=\"LittleEndian\">"
+        )?;
+        writeln!(
+            writer,
+            "<PolyData>\n<Piece NumberOfPoints=\"{point_count}\" NumberOfLines=\"{}\">",
+            self.polylines.len()
+        )?;
+        writeln!(writer, "<Points>")?;
+        export::write_vtk_vectors(
+            &mut writer,
+            "points",
+            self.polylines
+                .iter()
+                .flat_map(|polyline| polyline.points.iter().map(|point| point.to_array())),
+        )?;
+        writeln!(writer, "</Points>\n<Lines>")?;
+
+        let mut connectivity = Vec::new();
+        let mut offsets = Vec::with_capacity(self.polylines.len());
+        let mut first = 0;
+        for polyline in &self.polylines {
+            connectivity.extend(first..first + polyline.points.len());
+            if polyline.closed {
+                connectivity.push(first);
+            }
+            offsets.push(connectivity.len());
+            first += polyline.points.len();
+        }
+        export::write_vtk_indices(&mut writer, "connectivity", &connectivity)?;
+        export::write_vtk_indices(&mut writer, "offsets", &offsets)?;
+        writeln!(writer, "</Lines>\n</Piece>\n</PolyData>\n</VTKFile>")?;
+
+        writer.flush()
+    }
+}
+
+impl SphSimulation {
+    pub fn extract_surface(&self, field: SurfaceField, cols: usize, rows: usize) -> FreeSurface {
+        let cols = cols.max(2);
+        let rows = rows.max(2);
+        let values = self.sample_nodes(cols, rows, |grid, position| match field {
+            SurfaceField::ColorField { iso_level } => self.color_field(grid, position) - iso_level,
+            SurfaceField::ZhuBridson { particle_radius } => {
+                self.zhu_bridson_field(grid, position, particle_radius)
+            }
+        });
+
+        FreeSurface {
+            bounds: self.config.bounds,
+            polylines: marching_squares(self.config.bounds, cols, rows, &values),
+        }
+    }
+
+    fn color_field(&self, grid: &NeighborGrid, position: Vector) -> Real {
+        let mut value = 0.0;
+
+        grid.for_each_near(position, |neighbor| {
+            let distance_sq = (position - self.positions[neighbor]).length_squared();
+            value += self.config.particle_mass
+                * self.inv_densities[neighbor]
+                * self.kernels.poly6(distance_sq);
+        });
+
+        value
+    }
+
+    fn zhu_bridson_field(
+        &self,
+        grid: &NeighborGrid,
+        position: Vector,
+        particle_radius: Real,
+    ) -> Real {
+        let inv_support_sq = self.kernels.support_sq.recip();
+        let mut weight_sum = 0.0;
+        let mut weighted_position = Vector::ZERO;
+
+        grid.for_each_near(position, |neighbor| {
+            let neighbor_position = self.positions[neighbor];
+            let s = 1.0 - (position - neighbor_position).length_squared() * inv_support_sq;
+
+            if s > 0.0 {
+                let weight = s * s * s;
+                weight_sum += weight;
+                weighted_position += weight * neighbor_position;
+            }
+        });
+
+        if weight_sum <= EPSILON {
+            return -self.kernels.support_radius;
+        }
+
+        particle_radius - (position - weighted_position / weight_sum).length()
+    }
+}
+
+fn marching_squares(
+    bounds: Rect<Real>,
+    cols: usize,
+    rows: usize,
+    values: &[Real],
+) -> Vec<Polyline> {
+    let spacing = Vector::new(
+        bounds.w() / (cols - 1) as Real,
+        bounds.h() / (rows - 1) as Real,
+    );
+    let origin = Vector::new(bounds.left(), bounds.bottom());
+    let node = |col: usize, row: usize| row * cols + col;
+    let horizontal_edge = |col: usize, row: usize| 2 * node(col, row);
+    let vertical_edge = |col: usize, row: usize| 2 * node(col, row) + 1;
+
+    let mut crossings = HashMap::new();
+    let mut segments = Vec::new();
+    let mut crossing = |edge: usize, a: usize, b: usize| {
+        crossings.entry(edge).or_insert_with(|| {
+            let position_a = origin + Vector::new((a % cols) as Real, (a / cols) as Real) * spacing;
+            let position_b = origin + Vector::new((b % cols) as Real, (b / cols) as Real) * spacing;
+            let t = values[a] / (values[a] - values[b]);
+            position_a + (position_b - position_a) * t.clamp(0.0, 1.0)
+        });
+        edge
+    };
+
+    for row in 0..rows - 1 {
+        for col in 0..cols - 1 {
+            let corners = [
+                node(col, row),
+                node(col + 1, row),
+                node(col + 1, row + 1),
+                node(col, row + 1),
+            ];
+            let inside = corners.map(|corner| values[corner] > 0.0);
+            let case = inside
+                .iter()
+                .enumerate()
+                .fold(0, |case, (bit, inside)| case | ((*inside as u8) << bit));
+
+            if case == 0 || case == 15 {
+                continue;
+            }
+
+            let bottom = (inside[0] != inside[1])
+                .then(|| crossing(horizontal_edge(col, row), corners[0], corners[1]));
+            let right = (inside[1] != inside[2])
+                .then(|| crossing(vertical_edge(col + 1, row), corners[1], corners[2]));
+            let top = (inside[3] != inside[2])
+                .then(|| crossing(horizontal_edge(col, row + 1), corners[3], corners[2]));
+            let left = (inside[0] != inside[3])
+                .then(|| crossing(vertical_edge(col, row), corners[0], corners[3]));
+
+            match (bottom, right, top, left) {
+                (Some(bottom), Some(right), Some(top), Some(left)) => {
+                    let center = corners.iter().map(|corner| values[*corner]).sum::<Real>();
+                    if (case == 5) == (center > 0.0) {
+                        segments.push((bottom, right));
+                        segments.push((top, left));
+                    } else {
+                        segments.push((left, bottom));
+                        segments.push((right, top));
+                    }
+                }
+                (a, b, c, d) => {
+                    let mut ends = [a, b, c, d].into_iter().flatten();
+                    if let (Some(start), Some(end)) = (ends.next(), ends.next()) {
+                        segments.push((start, end));
+                    }
+                }
+            }
+        }
+    }
+
+    join_segments(&segments)
+        .into_iter()
+        .map(|(edges, closed)| Polyline {
+            points: edges.iter().map(|edge| crossings[edge]).collect(),
+            closed,
+        })
+        .collect()
+}
+
+fn join_segments(segments: &[(usize, usize)]) -> Vec<(Vec<usize>, bool)> {
+    let mut touching: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, (start, end)) in segments.iter().enumerate() {
+        touching.entry(*start).or_default().push(index);
+        touching.entry(*end).or_default().push(index);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut chains = Vec::new();
+
+    for first in 0..segments.len() {
+        if used[first] {
+            continue;
+        }
+
+        used[first] = true;
+        let mut chain = vec![segments[first].0, segments[first].1];
+
+        for _ in 0..2 {
+            while let Some(next) = touching[chain.last().unwrap()]
+                .iter()
+                .copied()
+                .find(|segment| !used[*segment])
+            {
+                used[next] = true;
+                let (start, end) = segments[next];
+                chain.push(if start == *chain.last().unwrap() {
+                    end
+                } else {
+                    start
+                });
+            }
+            chain.reverse();
+        }
+
+        let closed = chain.len() > 3 && chain.first() == chain.last();
+        if closed {
+            chain.pop();
+        }
+        chains.push((chain, closed));
+    }
+
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+
+    #[test]
+    fn resting_block_has_closed_outline_at_its_top() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 10, Vector::new(-1.0, -1.0));
+        let top = -1.0 + 9.0 * config.particle_spacing;
+
+        let surface =
+            simulation.extract_surface(SurfaceField::ColorField { iso_level: 0.5 }, 81, 81);
+        let elevation = surface.elevation(-0.5).unwrap();
+
+        assert_eq!(surface.polylines.len(), 1);
+        assert!(surface.polylines[0].closed);
+        assert!((elevation - top).abs() < config.particle_spacing);
+        assert!(surface.elevation(1.5).is_none());
+    }
+}
@@ -0,0 +1,187 @@
+use super::{KernelSet, Real, SphConfig, SphSimulation, Vector};
+use nannou::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceTensionModel {
+    ColorField,
+    Akinci,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wall {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl Wall {
+    pub const ALL: [Self; 4] = [Self::Left, Self::Right, Self::Bottom, Self::Top];
+
+    pub fn normal(self) -> Vector {
+        match self {
+            Self::Left => Vector::new(1.0, 0.0),
+            Self::Right => Vector::new(-1.0, 0.0),
+            Self::Bottom => Vector::new(0.0, 1.0),
+            Self::Top => Vector::new(0.0, -1.0),
+        }
+    }
+
+    pub fn distance(self, bounds: Rect<Real>, position: Vector) -> Real {
+        match self {
+            Self::Left => position.x - bounds.left(),
+            Self::Right => bounds.right() - position.x,
+            Self::Bottom => position.y - bounds.bottom(),
+            Self::Top => bounds.top() - position.y,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WallMaterial {
+    pub adhesion: Real,
+    pub contact_angle: Option<Real>,
+}
+
+impl WallMaterial {
+    pub fn effective_adhesion(&self, surface_tension: Real) -> Real {
+        match self.contact_angle {
+            Some(angle) => surface_tension * (1.0 + angle.cos()),
+            None => self.adhesion,
+        }
+    }
+}
+
+impl SphSimulation {
+    #[inline]
+    pub(super) fn akinci_tension(
+        config: SphConfig,
+        kernels: KernelSet,
+        delta: Vector,
+        distance: Real,
+        density_ratio: Real,
+        normal_difference: Vector,
+    ) -> Vector {
+        let cohesion = config.particle_mass * kernels.cohesion(distance) * delta / distance;
+        let curvature = config.smoothing_radius * normal_difference;
+
+        -config.surface_tension * density_ratio * (cohesion + curvature)
+    }
+
+    pub(super) fn wall_adhesion(config: SphConfig, kernels: KernelSet, position: Vector) -> Vector {
+        let spacing = config.particle_spacing;
+        let wall_mass = config.rest_density * spacing * spacing;
+        let reach = (kernels.support_radius / spacing).ceil() as i32;
+        let mut acceleration = Vector::ZERO;
+
+        for wall in Wall::ALL {
+            let adhesion =
+                config.wall_materials[wall as usize].effective_adhesion(config.surface_tension);
+            let distance = wall.distance(config.bounds, position);
+            if adhesion == 0.0 || distance >= kernels.support_radius {
+                continue;
+            }
+
+            let mut pull = 0.0;
+            for offset in -reach..=reach {
+                let tangential = offset as Real * spacing;
+                let radius = (distance * distance + tangential * tangential).sqrt();
+                pull += kernels.adhesion(radius) * distance / radius.max(Real::EPSILON);
+            }
+
+            acceleration -= adhesion * wall_mass * pull * wall.normal();
+        }
+
+        acceleration
+    }
+
+    pub(super) fn wetting_normal(
+        config: SphConfig,
+        position: Vector,
+        surface_normal: Vector,
+    ) -> Vector {
+        let mut normal = surface_normal;
+
+        for wall in Wall::ALL {
+            let Some(angle) = config.wall_materials[wall as usize].contact_angle else {
+                continue;
+            };
+            if wall.distance(config.bounds, position) >= config.smoothing_radius {
+                continue;
+            }
+
+            normal = Self::contact_normal(wall, normal, angle);
+        }
+
+        normal
+    }
+
+    #[inline]
+    pub(super) fn contact_normal(wall: Wall, surface_normal: Vector, angle: Real) -> Vector {
+        let wall_normal = wall.normal();
+        let outward = -surface_normal;
+        let tangent = outward - outward.dot(wall_normal) * wall_normal;
+        let tangent_sq = tangent.length_squared();
+        if tangent_sq <= Real::EPSILON {
+            return surface_normal;
+        }
+
+        let target = wall_normal * angle.cos() + tangent / tangent_sq.sqrt() * angle.sin();
+        -target * surface_normal.length()
+    }
+
+    #[inline]
+    pub(super) fn contact_angle(wall: Wall, surface_normal: Vector) -> Option<Real> {
+        let length = surface_normal.length();
+        if length <= Real::EPSILON {
+            return None;
+        }
+
+        let cosine = (-surface_normal / length).dot(wall.normal());
+        Some(cosine.clamp(-1.0, 1.0).acos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cohesion_attracts_at_range_and_adhesion_pulls_toward_the_wall() {
+        let mut config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        config.wall_materials[Wall::Bottom as usize].adhesion = 1.0;
+        let kernels = KernelSet::new(config.smoothing_radius);
+        let distance = 0.8 * config.smoothing_radius;
+
+        let tension = SphSimulation::akinci_tension(
+            config,
+            kernels,
+            Vector::new(distance, 0.0),
+            distance,
+            1.0,
+            Vector::ZERO,
+        );
+        assert!(tension.x < 0.0);
+
+        let near_floor = Vector::new(0.0, config.bounds.bottom() + 0.7 * config.smoothing_radius);
+        let adhesion = SphSimulation::wall_adhesion(config, kernels, near_floor);
+        assert!(adhesion.y < 0.0);
+        assert_eq!(adhesion.x, 0.0);
+
+        let far = SphSimulation::wall_adhesion(config, kernels, Vector::ZERO);
+        assert_eq!(far, Vector::ZERO);
+    }
+
+    #[test]
+    fn contact_normal_imposes_the_static_angle() {
+        let angle = Real::to_radians(60.0);
+        let spreading_drop_edge = Vector::new(1.0, 0.2);
+
+        let corrected = SphSimulation::contact_normal(Wall::Bottom, spreading_drop_edge, angle);
+
+        let measured = SphSimulation::contact_angle(Wall::Bottom, corrected).unwrap();
+        assert!((measured - angle).abs() < 1.0e-4);
+        assert!(corrected.x > 0.0);
+        assert!((corrected.length() - spreading_drop_edge.length()).abs() < 1.0e-5);
+    }
+}
@@ -0,0 +1,125 @@
+use super::{EPSILON, MIN_PAR_CHUNK, NeighborSearch, Real, SphSimulation, Vector};
+use rayon::prelude::*;
+
+impl SphSimulation {
+    pub fn vorticities(&self) -> &[Real] {
+        &self.vorticities
+    }
+
+    pub fn refresh_vorticities(&mut self) {
+        if self.vorticities_stale {
+            self.refresh_neighbors();
+            self.compute_vorticities();
+        }
+    }
+
+    pub(super) fn compute_vorticities(&mut self) {
+        let kernels = self.kernels;
+        let particle_mass = self.config.particle_mass;
+        let positions = &self.positions;
+        let velocities = &self.velocities;
+        let inv_densities = &self.inv_densities;
+        let correction_enabled = self.config.kernel_gradient_correction;
+        let gradient_corrections = &self.gradient_corrections;
+        let grid = &self.grid;
+
+        self.vorticities
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(MIN_PAR_CHUNK)
+            .for_each(|(index, vorticity)| {
+                let position_i = positions[index];
+                let velocity_i = velocities[index];
+                let correction = gradient_corrections[index];
+                let mut curl = 0.0;
+
+                grid.for_each_neighbor(index, |neighbor| {
+                    let delta = position_i - positions[neighbor];
+                    let distance_sq = delta.length_squared();
+
+                    if distance_sq >= kernels.support_sq || distance_sq <= EPSILON {
+                        return;
+                    }
+
+                    let mut gradient = kernels.spiky_gradient(delta, distance_sq.sqrt());
+                    if correction_enabled {
+                        gradient = correction.transform(gradient);
+                    }
+                    curl += particle_mass
+                        * inv_densities[neighbor]
+                        * gradient.perp_dot(velocities[neighbor] - velocity_i);
+                });
+
+                *vorticity = curl;
+            });
+        self.vorticities_stale = false;
+    }
+
+    #[inline]
+    pub(super) fn confinement_acceleration(
+        epsilon: Real,
+        vorticity: Real,
+        vorticity_gradient: Vector,
+    ) -> Vector {
+        let length_sq = vorticity_gradient.length_squared();
+        if length_sq <= EPSILON {
+            return Vector::ZERO;
+        }
+
+        let location = vorticity_gradient / length_sq.sqrt();
+        epsilon * vorticity * Vector::new(location.y, -location.x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SphConfig;
+    use super::*;
+    use nannou::prelude::*;
+
+    #[test]
+    fn rigid_rotation_has_twice_the_angular_velocity_as_vorticity() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        let angular_velocity = 1.5;
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        for (velocity, position) in simulation
+            .velocities
+            .iter_mut()
+            .zip(simulation.positions.iter())
+        {
+            *velocity = angular_velocity * Vector::new(-position.y, position.x);
+        }
+
+        simulation.grid.rebuild(&simulation.positions);
+        simulation.compute_densities();
+        simulation.compute_pressures();
+        simulation.compute_vorticities();
+
+        let center = simulation.vorticities()[10 * 20 + 10];
+        assert!((center - 2.0 * angular_velocity).abs() < 0.2 * 2.0 * angular_velocity);
+    }
+
+    #[test]
+    fn lazy_vorticities_see_particles_moved_after_the_step() {
+        let config = SphConfig::new(Rect::from_w_h(4.0, 4.0));
+        let mut simulation = SphSimulation::new(config);
+        simulation.seed_block(20, 20, Vector::new(-0.5, -0.5));
+        simulation.step(1.0 / 240.0, None);
+        for (velocity, position) in simulation
+            .velocities
+            .iter_mut()
+            .zip(simulation.positions.iter_mut())
+        {
+            *position *= 0.6;
+            *velocity = Vector::new(-position.y, position.x);
+        }
+
+        let mut eager = simulation.clone();
+        eager.rebuild_neighbors();
+        eager.compute_vorticities();
+        simulation.refresh_vorticities();
+
+        assert_eq!(simulation.vorticities, eager.vorticities);
+    }
+}